@@ -0,0 +1,135 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Database extension for the `federation_outbox` table.
+
+use chrono::{DateTime, Utc};
+use oxidetalis_entities::prelude::*;
+use sea_orm::{
+    sea_query::{Expr, Query},
+    DatabaseConnection,
+};
+
+use crate::{errors::ServerResult, schemas::FederationEvent};
+
+/// Extension trait for the `federation_outbox` table.
+pub trait FederationOutboxExt {
+    /// Add the event to the outbox, to be delivered to the remote homeserver
+    /// later. The `request_id` is the id of the client event that caused it.
+    async fn push_federation_event(
+        &self,
+        sender: &UserModel,
+        server: &str,
+        event: &FederationEvent,
+        request_id: Option<String>,
+    ) -> ServerResult<()>;
+
+    /// Returns the servers that their oldest outbox event is due to be
+    /// delivered, the events to the same server are delivered in order, so the
+    /// newer events wait for the oldest one
+    async fn due_federation_servers(&self, limit: u64) -> ServerResult<Vec<String>>;
+
+    /// Returns the outbox events to the server, with their senders, ordered by
+    /// their time in the outbox (oldest first)
+    async fn federation_events_to(
+        &self,
+        server: &str,
+        limit: u64,
+    ) -> ServerResult<Vec<(FederationOutboxModel, Option<UserModel>)>>;
+
+    /// Reschedule the outbox event after a failed delivery attempt
+    async fn reschedule_federation_event(
+        &self,
+        event: FederationOutboxModel,
+        next_retry_at: DateTime<Utc>,
+    ) -> ServerResult<()>;
+}
+
+impl FederationOutboxExt for DatabaseConnection {
+    #[logcall::logcall]
+    async fn push_federation_event(
+        &self,
+        sender: &UserModel,
+        server: &str,
+        event: &FederationEvent,
+        request_id: Option<String>,
+    ) -> ServerResult<()> {
+        let now = Utc::now();
+        FederationOutboxActiveModel {
+            sender_id: Set(sender.id),
+            server: Set(server.to_owned()),
+            payload: Set(serde_json::to_string(event).expect("Can't fail")),
+            request_id: Set(request_id),
+            attempts: Set(0),
+            created_at: Set(now),
+            next_retry_at: Set(now),
+            ..Default::default()
+        }
+        .save(self)
+        .await?;
+        Ok(())
+    }
+
+    async fn due_federation_servers(&self, limit: u64) -> ServerResult<Vec<String>> {
+        FederationOutboxEntity::find()
+            .select_only()
+            .column(FederationOutboxColumn::Server)
+            .filter(
+                FederationOutboxColumn::Id.in_subquery(
+                    Query::select()
+                        .expr(Expr::col(FederationOutboxColumn::Id).min())
+                        .from(FederationOutboxEntity)
+                        .group_by_col(FederationOutboxColumn::Server)
+                        .to_owned(),
+                ),
+            )
+            .filter(FederationOutboxColumn::NextRetryAt.lte(Utc::now()))
+            .order_by_asc(FederationOutboxColumn::Id)
+            .limit(limit)
+            .into_tuple::<String>()
+            .all(self)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn federation_events_to(
+        &self,
+        server: &str,
+        limit: u64,
+    ) -> ServerResult<Vec<(FederationOutboxModel, Option<UserModel>)>> {
+        FederationOutboxEntity::find()
+            .filter(FederationOutboxColumn::Server.eq(server))
+            .order_by_asc(FederationOutboxColumn::Id)
+            .limit(limit)
+            .find_also_related(UserEntity)
+            .all(self)
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn reschedule_federation_event(
+        &self,
+        event: FederationOutboxModel,
+        next_retry_at: DateTime<Utc>,
+    ) -> ServerResult<()> {
+        let attempts = event.attempts.saturating_add(1);
+        let mut event = event.into_active_model();
+        event.attempts = Set(attempts);
+        event.next_retry_at = Set(next_retry_at);
+        event.update(self).await?;
+        Ok(())
+    }
+}
@@ -16,20 +16,31 @@
 
 //! Database extension for the `incoming_chat` table.
 
+use std::num::{NonZeroU32, NonZeroU8};
+
 use chrono::Utc;
-use oxidetalis_core::types::PublicKey;
+use oxidetalis_core::types::Signature;
 use oxidetalis_entities::prelude::*;
-use sea_orm::{sea_query::OnConflict, DatabaseConnection};
+use sea_orm::{sea_query::OnConflict, DatabaseConnection, DbErr};
 
-use crate::errors::ServerResult;
+use crate::{errors::ServerResult, federation::UserAddress};
 
 /// Extension trait for the `incoming_chat` table.
 pub trait IncomingChatExt {
-    /// Save the incoming chat request
+    /// Save the incoming chat request with the sender ECDSA signature if they
+    /// signed it, returns `None` if the same request is already saved
     async fn save_in_chat_request(
         &self,
         chat_request_recipient: &UserModel,
-        chat_request_sender: &PublicKey,
+        chat_request_sender: &UserAddress,
+        signature: Option<Signature>,
+    ) -> ServerResult<Option<IncomingChatModel>>;
+
+    /// Remove the incoming chat request, if it is not delivered yet
+    async fn remove_in_chat_request(
+        &self,
+        chat_request_recipient: &UserModel,
+        chat_request_sender: &UserAddress,
     ) -> ServerResult<()>;
 
     /// Returns all incoming chat requests for the given recipient
@@ -38,19 +49,28 @@ pub trait IncomingChatExt {
         chat_request_recipient: &UserModel,
     ) -> ServerResult<Vec<IncomingChatModel>>;
 
-    /// Save the incoming chat response
+    /// Save the incoming chat response, returns `None` if the same response is
+    /// already saved
     async fn save_in_chat_response(
         &self,
         chat_response_recipient: &UserModel,
-        chat_response_sender: &PublicKey,
+        chat_response_sender: &UserAddress,
         accepted_response: bool,
-    ) -> ServerResult<()>;
+    ) -> ServerResult<Option<IncomingChatModel>>;
 
     /// Returns all incoming chat responses for the given recipient
     async fn get_all_chat_responses(
         &self,
         chat_response_recipient: &UserModel,
     ) -> ServerResult<Vec<IncomingChatModel>>;
+
+    /// Returns the incoming chat requests of the given recipient, paginated
+    async fn user_incoming_chat_requests(
+        &self,
+        chat_request_recipient: &UserModel,
+        page: NonZeroU32,
+        page_size: NonZeroU8,
+    ) -> ServerResult<Vec<IncomingChatModel>>;
 }
 
 impl IncomingChatExt for DatabaseConnection {
@@ -58,9 +78,40 @@ impl IncomingChatExt for DatabaseConnection {
     async fn save_in_chat_request(
         &self,
         chat_request_recipient: &UserModel,
-        chat_request_sender: &PublicKey,
+        chat_request_sender: &UserAddress,
+        signature: Option<Signature>,
+    ) -> ServerResult<Option<IncomingChatModel>> {
+        save(
+            self,
+            chat_request_recipient,
+            chat_request_sender,
+            None,
+            signature,
+        )
+        .await
+    }
+
+    #[logcall::logcall]
+    async fn remove_in_chat_request(
+        &self,
+        chat_request_recipient: &UserModel,
+        chat_request_sender: &UserAddress,
     ) -> ServerResult<()> {
-        save(self, chat_request_recipient, chat_request_sender, None).await
+        IncomingChatEntity::delete_many()
+            .filter(IncomingChatColumn::RecipientId.eq(chat_request_recipient.id))
+            .filter(IncomingChatColumn::Sender.eq(chat_request_sender.public_key))
+            .filter(
+                chat_request_sender
+                    .server
+                    .as_deref()
+                    .map_or(IncomingChatColumn::SenderServer.is_null(), |server| {
+                        IncomingChatColumn::SenderServer.eq(server)
+                    }),
+            )
+            .filter(IncomingChatColumn::AcceptedResponse.is_null())
+            .exec(self)
+            .await?;
+        Ok(())
     }
 
     async fn get_all_chat_requests(
@@ -74,14 +125,15 @@ impl IncomingChatExt for DatabaseConnection {
     async fn save_in_chat_response(
         &self,
         chat_response_recipient: &UserModel,
-        chat_response_sender: &PublicKey,
+        chat_response_sender: &UserAddress,
         accepted_response: bool,
-    ) -> ServerResult<()> {
+    ) -> ServerResult<Option<IncomingChatModel>> {
         save(
             self,
             chat_response_recipient,
             chat_response_sender,
             Some(accepted_response),
+            None,
         )
         .await
     }
@@ -92,20 +144,39 @@ impl IncomingChatExt for DatabaseConnection {
     ) -> ServerResult<Vec<IncomingChatModel>> {
         get_all::<false>(self, chat_response_recipient).await
     }
+
+    async fn user_incoming_chat_requests(
+        &self,
+        chat_request_recipient: &UserModel,
+        page: NonZeroU32,
+        page_size: NonZeroU8,
+    ) -> ServerResult<Vec<IncomingChatModel>> {
+        chat_request_recipient
+            .find_related(IncomingChatEntity)
+            .filter(IncomingChatColumn::AcceptedResponse.is_null())
+            .order_by_asc(IncomingChatColumn::Id)
+            .paginate(self, u64::from(page_size.get()))
+            .fetch_page(u64::from(page.get() - 1))
+            .await
+            .map_err(Into::into)
+    }
 }
 
 /// Utility function to save incoming chat request or response
 async fn save(
     db: &DatabaseConnection,
     recipient: &UserModel,
-    sender: &PublicKey,
+    sender: &UserAddress,
     accepted_response: Option<bool>,
-) -> ServerResult<()> {
-    IncomingChatEntity::insert(IncomingChatActiveModel {
+    signature: Option<Signature>,
+) -> ServerResult<Option<IncomingChatModel>> {
+    let result = IncomingChatEntity::insert(IncomingChatActiveModel {
         recipient_id: Set(recipient.id),
-        sender: Set(*sender),
+        sender: Set(sender.public_key),
+        sender_server: Set(sender.server.clone()),
         received_timestamp: Set(Utc::now()),
         accepted_response: Set(accepted_response),
+        signature: Set(signature),
         ..Default::default()
     })
     .on_conflict(
@@ -117,9 +188,13 @@ async fn save(
         .do_nothing()
         .to_owned(),
     )
-    .exec(db)
-    .await?;
-    Ok(())
+    .exec_with_returning(db)
+    .await;
+    match result {
+        Ok(incoming_chat) => Ok(Some(incoming_chat)),
+        Err(DbErr::RecordNotInserted) => Ok(None),
+        Err(err) => Err(err.into()),
+    }
 }
 
 /// Utility function to get all incoming chat requests or responses
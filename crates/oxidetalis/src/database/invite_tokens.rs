@@ -0,0 +1,126 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Database extension for the `invite_tokens` table.
+
+use std::num::{NonZeroU32, NonZeroU8};
+
+use base58::ToBase58;
+use chrono::{DateTime, Utc};
+use oxidetalis_entities::prelude::*;
+use sea_orm::{sea_query::Expr, ConnectionTrait};
+
+use crate::{errors::ServerResult, routes::ApiError};
+
+/// Length of the invite token in bytes, before encoding it in base58
+const INVITE_TOKEN_LENGTH: usize = 32;
+
+/// Extension trait for the `invite_tokens` table.
+pub trait InviteTokensExt {
+    /// Mint new invite token, the token can be used `max_uses` times before
+    /// `expires_at`
+    async fn mint_invite_token(
+        &self,
+        creator: &UserModel,
+        max_uses: i32,
+        expires_at: DateTime<Utc>,
+    ) -> ServerResult<InviteTokensModel>;
+
+    /// Returns the invite tokens, the newest first
+    async fn invite_tokens(
+        &self,
+        page: NonZeroU32,
+        page_size: NonZeroU8,
+    ) -> ServerResult<Vec<InviteTokensModel>>;
+
+    /// Revoke the invite token, returns [`ApiError::InviteTokenNotFound`] if
+    /// there is no such token
+    async fn revoke_invite_token(&self, token: &str) -> ServerResult<()>;
+
+    /// Use the invite token once, returns `false` if the token doesn't exist,
+    /// is expired or is used up
+    async fn use_invite_token(&self, token: &str) -> ServerResult<bool>;
+}
+
+impl<C: ConnectionTrait> InviteTokensExt for C {
+    #[logcall::logcall]
+    async fn mint_invite_token(
+        &self,
+        creator: &UserModel,
+        max_uses: i32,
+        expires_at: DateTime<Utc>,
+    ) -> ServerResult<InviteTokensModel> {
+        InviteTokensActiveModel {
+            creator_id: Set(creator.id),
+            token: Set(rand::random::<[u8; INVITE_TOKEN_LENGTH]>().to_base58()),
+            max_uses: Set(max_uses),
+            uses: Set(0),
+            created_at: Set(Utc::now()),
+            expires_at: Set(expires_at),
+            ..Default::default()
+        }
+        .insert(self)
+        .await
+        .map_err(Into::into)
+    }
+
+    async fn invite_tokens(
+        &self,
+        page: NonZeroU32,
+        page_size: NonZeroU8,
+    ) -> ServerResult<Vec<InviteTokensModel>> {
+        InviteTokensEntity::find()
+            .order_by_desc(InviteTokensColumn::Id)
+            .paginate(self, u64::from(page_size.get()))
+            .fetch_page(u64::from(page.get() - 1))
+            .await
+            .map_err(Into::into)
+    }
+
+    #[logcall::logcall]
+    async fn revoke_invite_token(&self, token: &str) -> ServerResult<()> {
+        let result = InviteTokensEntity::delete_many()
+            .filter(InviteTokensColumn::Token.eq(token))
+            .exec(self)
+            .await?;
+        if result.rows_affected == 0 {
+            return Err(ApiError::InviteTokenNotFound.into());
+        }
+        Ok(())
+    }
+
+    async fn use_invite_token(&self, token: &str) -> ServerResult<bool> {
+        // A single update, so the concurrent registrations can't use the token
+        // more than its maximum uses
+        let result = InviteTokensEntity::update_many()
+            .col_expr(
+                InviteTokensColumn::Uses,
+                Expr::col(InviteTokensColumn::Uses).add(1),
+            )
+            .filter(
+                InviteTokensColumn::Token
+                    .eq(token)
+                    .and(InviteTokensColumn::ExpiresAt.gt(Utc::now()))
+                    .and(
+                        Expr::col(InviteTokensColumn::Uses)
+                            .lt(Expr::col(InviteTokensColumn::MaxUses)),
+                    ),
+            )
+            .exec(self)
+            .await?;
+        Ok(result.rows_affected == 1)
+    }
+}
@@ -16,12 +16,18 @@
 
 //! Database trait extensions.
 
+mod federation_outbox;
 mod incoming_chat;
+mod invite_tokens;
 mod out_chat_requests;
+mod queued_events;
 mod user;
 mod user_status;
 
+pub use federation_outbox::*;
 pub use incoming_chat::*;
+pub use invite_tokens::*;
 pub use out_chat_requests::*;
+pub use queued_events::*;
 pub use user::*;
 pub use user_status::*;
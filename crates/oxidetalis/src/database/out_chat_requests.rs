@@ -16,12 +16,14 @@
 
 //! Database extension for the `out_chat_requests` table.
 
+use std::num::{NonZeroU32, NonZeroU8};
+
 use chrono::Utc;
 use oxidetalis_core::types::PublicKey;
 use oxidetalis_entities::prelude::*;
 use sea_orm::DatabaseConnection;
 
-use crate::{errors::ServerResult, websocket::errors::WsError};
+use crate::{errors::ServerResult, federation::UserAddress, websocket::errors::WsError};
 
 /// Extension trait for the `out_chat_requests` table.
 pub trait OutChatRequestsExt {
@@ -33,11 +35,12 @@ pub trait OutChatRequestsExt {
         recipient: &PublicKey,
     ) -> ServerResult<Option<OutChatRequestsModel>>;
 
-    /// Save the chat request in the requester table
+    /// Save the chat request in the requester table, with the recipient
+    /// homeserver if they are a remote user
     async fn save_out_chat_request(
         &self,
         requester: &UserModel,
-        recipient: &PublicKey,
+        recipient: &UserAddress,
     ) -> ServerResult<()>;
 
     /// Remove the chat request from requester table
@@ -46,6 +49,14 @@ pub trait OutChatRequestsExt {
         requester: &UserModel,
         recipient: &PublicKey,
     ) -> ServerResult<()>;
+
+    /// Returns the chat requests sent by the user, that are not answered yet
+    async fn user_outgoing_chat_requests(
+        &self,
+        requester: &UserModel,
+        page: NonZeroU32,
+        page_size: NonZeroU8,
+    ) -> ServerResult<Vec<OutChatRequestsModel>>;
 }
 
 impl OutChatRequestsExt for DatabaseConnection {
@@ -67,11 +78,12 @@ impl OutChatRequestsExt for DatabaseConnection {
     async fn save_out_chat_request(
         &self,
         requester: &UserModel,
-        recipient: &PublicKey,
+        recipient: &UserAddress,
     ) -> ServerResult<()> {
         if let Err(err) = (OutChatRequestsActiveModel {
             sender_id: Set(requester.id),
-            recipient: Set(*recipient),
+            recipient: Set(recipient.public_key),
+            recipient_server: Set(recipient.server.clone()),
             out_on: Set(Utc::now()),
             ..Default::default()
         }
@@ -99,4 +111,19 @@ impl OutChatRequestsExt for DatabaseConnection {
         }
         Ok(())
     }
+
+    async fn user_outgoing_chat_requests(
+        &self,
+        requester: &UserModel,
+        page: NonZeroU32,
+        page_size: NonZeroU8,
+    ) -> ServerResult<Vec<OutChatRequestsModel>> {
+        requester
+            .find_related(OutChatRequestsEntity)
+            .order_by_asc(OutChatRequestsColumn::Id)
+            .paginate(self, u64::from(page_size.get()))
+            .fetch_page(u64::from(page.get() - 1))
+            .await
+            .map_err(Into::into)
+    }
 }
@@ -0,0 +1,137 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Database extension for the `queued_events` table.
+
+use chrono::{DateTime, TimeDelta, Utc};
+use oxidetalis_config::OfflineQueue;
+use oxidetalis_entities::prelude::*;
+use sea_orm::{sea_query::Expr, ConnectionTrait, DatabaseConnection, TransactionTrait};
+
+use crate::{
+    errors::ServerResult,
+    websocket::{errors::WsError, ServerEventType},
+};
+
+/// Extension trait for the `queued_events` table.
+pub trait QueuedEventsExt {
+    /// Queue the event for the offline recipient, the expired events of the
+    /// recipient will be removed first. The recipient row is locked until the
+    /// event is queued, so the concurrent senders can't exceed the queue size.
+    ///
+    /// Returns the queued event, or [`WsError::RecipientQueueFull`] if the
+    /// recipient queue size will exceed the maximum size.
+    async fn queue_event(
+        &self,
+        recipient: &UserModel,
+        event: &ServerEventType,
+        queue_config: &OfflineQueue,
+    ) -> ServerResult<QueuedEventsModel>;
+
+    /// Returns the unexpired queued events of the recipient, ordered by the
+    /// queue time (oldest first). The expired events will be removed.
+    async fn get_queued_events(
+        &self,
+        recipient: &UserModel,
+        queue_config: &OfflineQueue,
+    ) -> ServerResult<Vec<QueuedEventsModel>>;
+}
+
+impl QueuedEventsExt for DatabaseConnection {
+    #[logcall::logcall]
+    async fn queue_event(
+        &self,
+        recipient: &UserModel,
+        event: &ServerEventType,
+        queue_config: &OfflineQueue,
+    ) -> ServerResult<QueuedEventsModel> {
+        let payload = serde_json::to_string(event).expect("Can't fail");
+        let txn = self.begin().await?;
+        // The lock is released when the transaction is committed or dropped
+        UserEntity::find_by_id(recipient.id)
+            .lock_exclusive()
+            .one(&txn)
+            .await?;
+        remove_expired_events(&txn, recipient, queue_config.ttl_secs).await?;
+
+        let queue_size = recipient
+            .find_related(QueuedEventsEntity)
+            .select_only()
+            .column_as(
+                Expr::cust("COALESCE(SUM(OCTET_LENGTH(payload)), 0)"),
+                "queue_size",
+            )
+            .into_tuple::<i64>()
+            .one(&txn)
+            .await?
+            .unwrap_or_default();
+        if usize::try_from(queue_size)
+            .unwrap_or(usize::MAX)
+            .saturating_add(payload.len())
+            > queue_config.max_size.as_bytes()
+        {
+            return Err(WsError::RecipientQueueFull.into());
+        }
+
+        let queued_event = QueuedEventsActiveModel {
+            recipient_id: Set(recipient.id),
+            payload: Set(payload),
+            queued_at: Set(Utc::now()),
+            ..Default::default()
+        }
+        .insert(&txn)
+        .await?;
+        txn.commit().await?;
+        Ok(queued_event)
+    }
+
+    async fn get_queued_events(
+        &self,
+        recipient: &UserModel,
+        queue_config: &OfflineQueue,
+    ) -> ServerResult<Vec<QueuedEventsModel>> {
+        remove_expired_events(self, recipient, queue_config.ttl_secs).await?;
+        recipient
+            .find_related(QueuedEventsEntity)
+            .order_by_asc(QueuedEventsColumn::Id)
+            .all(self)
+            .await
+            .map_err(Into::into)
+    }
+}
+
+/// Remove the expired queued events of the recipient
+async fn remove_expired_events(
+    db: &impl ConnectionTrait,
+    recipient: &UserModel,
+    ttl_secs: u64,
+) -> ServerResult<()> {
+    let expired_before = i64::try_from(ttl_secs)
+        .ok()
+        .and_then(TimeDelta::try_seconds)
+        .and_then(|ttl| Utc::now().checked_sub_signed(ttl))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC);
+
+    QueuedEventsEntity::delete_many()
+        .filter(
+            QueuedEventsColumn::RecipientId
+                .eq(recipient.id)
+                .and(QueuedEventsColumn::QueuedAt.lt(expired_before)),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
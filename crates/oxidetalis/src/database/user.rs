@@ -16,12 +16,15 @@
 
 //! Functions for interacting with the user table in the database.
 
+use std::num::{NonZeroU32, NonZeroU8};
+
 use chrono::Utc;
 use logcall::logcall;
 use oxidetalis_core::types::PublicKey;
 use oxidetalis_entities::prelude::*;
-use sea_orm::DatabaseConnection;
+use sea_orm::{ConnectionTrait, DatabaseConnection, TransactionTrait};
 
+use super::InviteTokensExt;
 use crate::{errors::ServerResult, routes::ApiError};
 
 pub trait UserTableExt {
@@ -29,8 +32,23 @@ pub trait UserTableExt {
     async fn users_exists_in_database(&self) -> ServerResult<bool>;
     /// Register new user
     async fn register_user(&self, public_key: &PublicKey, is_admin: bool) -> ServerResult<()>;
+    /// Register new user with an invite token, the token use is reverted if
+    /// the registration fails. Returns [`ApiError::InvalidInviteToken`] if the
+    /// token doesn't exist, is expired or is used up
+    async fn register_invited_user(
+        &self,
+        public_key: &PublicKey,
+        invite_token: &str,
+    ) -> ServerResult<()>;
     /// Returns user by its public key
     async fn get_user_by_pubk(&self, public_key: &PublicKey) -> ServerResult<Option<UserModel>>;
+    /// Returns the registered users, ordered by their registration
+    async fn users(&self, page: NonZeroU32, page_size: NonZeroU8) -> ServerResult<Vec<UserModel>>;
+    /// Ban or unban the user
+    async fn set_user_banned(&self, user: UserModel, is_banned: bool) -> ServerResult<()>;
+    /// Delete the user and all their data, with the other local users
+    /// whitelist/blacklist entries and chat requests of the user
+    async fn delete_user(&self, user: UserModel) -> ServerResult<()>;
 }
 
 impl UserTableExt for DatabaseConnection {
@@ -45,20 +63,23 @@ impl UserTableExt for DatabaseConnection {
 
     #[logcall]
     async fn register_user(&self, public_key: &PublicKey, is_admin: bool) -> ServerResult<()> {
-        if let Err(err) = (UserActiveModel {
-            public_key: Set(*public_key),
-            is_admin: Set(is_admin),
-            last_logout: Set(Utc::now()),
-            ..Default::default()
-        })
-        .save(self)
-        .await
-        {
-            if let Some(SqlErr::UniqueConstraintViolation(_)) = err.sql_err() {
-                return Err(ApiError::AlreadyRegistered.into());
-            }
-        }
+        insert_user(self, public_key, is_admin).await
+    }
 
+    #[logcall]
+    async fn register_invited_user(
+        &self,
+        public_key: &PublicKey,
+        invite_token: &str,
+    ) -> ServerResult<()> {
+        let txn = self.begin().await?;
+        if !txn.use_invite_token(invite_token).await? {
+            return Err(ApiError::InvalidInviteToken.into());
+        }
+        // The transaction is rolled back when it's dropped, so the token use
+        // is reverted if the user is already registered
+        insert_user(&txn, public_key, false).await?;
+        txn.commit().await?;
         Ok(())
     }
 
@@ -70,4 +91,81 @@ impl UserTableExt for DatabaseConnection {
             .await
             .map_err(Into::into)
     }
+
+    #[logcall]
+    async fn users(&self, page: NonZeroU32, page_size: NonZeroU8) -> ServerResult<Vec<UserModel>> {
+        UserEntity::find()
+            .order_by_asc(UserColumn::Id)
+            .paginate(self, u64::from(page_size.get()))
+            .fetch_page(u64::from(page.get() - 1))
+            .await
+            .map_err(Into::into)
+    }
+
+    #[logcall]
+    async fn set_user_banned(&self, user: UserModel, is_banned: bool) -> ServerResult<()> {
+        let mut user = user.into_active_model();
+        user.is_banned = Set(is_banned);
+        user.update(self).await?;
+        Ok(())
+    }
+
+    #[logcall]
+    async fn delete_user(&self, user: UserModel) -> ServerResult<()> {
+        let public_key = user.public_key;
+        let txn = self.begin().await?;
+        // The other local users data about the deleted user, their
+        // whitelists/blacklists and chat requests, are not linked to the user
+        // row, so they are not cascaded
+        UsersStatusEntity::delete_many()
+            .filter(UsersStatusColumn::Target.eq(public_key))
+            .exec(&txn)
+            .await?;
+        OutChatRequestsEntity::delete_many()
+            .filter(
+                OutChatRequestsColumn::Recipient
+                    .eq(public_key)
+                    .and(OutChatRequestsColumn::RecipientServer.is_null()),
+            )
+            .exec(&txn)
+            .await?;
+        IncomingChatEntity::delete_many()
+            .filter(
+                IncomingChatColumn::Sender
+                    .eq(public_key)
+                    .and(IncomingChatColumn::SenderServer.is_null()),
+            )
+            .exec(&txn)
+            .await?;
+        // The user own data are deleted with the user, the foreign keys cascade
+        user.delete(&txn).await?;
+        txn.commit().await?;
+        Ok(())
+    }
+}
+
+/// Insert new user, returns [`ApiError::AlreadyRegistered`] if the public key
+/// is already registered
+async fn insert_user(
+    db: &impl ConnectionTrait,
+    public_key: &PublicKey,
+    is_admin: bool,
+) -> ServerResult<()> {
+    if let Err(err) = (UserActiveModel {
+        public_key: Set(*public_key),
+        is_admin: Set(is_admin),
+        is_banned: Set(false),
+        last_logout: Set(Utc::now()),
+        ..Default::default()
+    })
+    .save(db)
+    .await
+    {
+        if let Some(SqlErr::UniqueConstraintViolation(_)) = err.sql_err() {
+            return Err(ApiError::AlreadyRegistered.into());
+        }
+        return Err(err.into());
+    }
+
+    Ok(())
 }
@@ -61,9 +61,6 @@ pub trait UsersStatusExt {
     ) -> ServerResult<()>;
 
     /// Remove the target from whitelist table
-    // FIXME(awiteb): This method will be used when I work on decentralization, So, I'm keeping it
-    // for now
-    #[allow(dead_code)]
     async fn remove_from_whitelist(
         &self,
         whitelister: &UserModel,
@@ -71,9 +68,6 @@ pub trait UsersStatusExt {
     ) -> ServerResult<()>;
 
     /// Remove the target from blacklist table
-    // FIXME(awiteb): This method will be used when I work on decentralization, So, I'm keeping it
-    // for now
-    #[allow(dead_code)]
     async fn remove_from_blacklist(
         &self,
         blacklister: &UserModel,
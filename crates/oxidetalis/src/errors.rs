@@ -66,6 +66,14 @@ impl From<ServerError> for ApiError {
     fn from(err: ServerError) -> Self {
         match err {
             ServerError::Ws(WsError::RegistredUserEvent) => ApiError::NotRegisteredUser,
+            ServerError::Ws(WsError::AlreadyOnTheWhitelist) => ApiError::AlreadyOnTheWhitelist,
+            ServerError::Ws(WsError::AlreadyOnTheBlacklist) => ApiError::AlreadyOnTheBlacklist,
+            ServerError::Ws(WsError::CannotAddSelfToWhitelist) => {
+                ApiError::CannotAddSelfToWhitelist
+            }
+            ServerError::Ws(WsError::CannotAddSelfToBlacklist) => {
+                ApiError::CannotAddSelfToBlacklist
+            }
             ServerError::Internal(_) | ServerError::Ws(_) => ApiError::Internal,
             ServerError::Api(err) => err,
         }
@@ -19,16 +19,19 @@
 use std::sync::Arc;
 
 use chrono::Utc;
+use dashmap::mapref::entry::Entry;
 use oxidetalis_config::Config;
 use oxidetalis_core::types::PublicKey;
-use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
 use salvo::Depot;
 use sea_orm::DatabaseConnection;
 use uuid::Uuid;
 
 use crate::{
+    federation::Federation,
     nonce::NonceCache,
-    websocket::{OnlineUsers, ServerEvent, SocketUserData, Unsigned},
+    registration::Registration,
+    secret_cache::SecretCache,
+    websocket::{OnlineUsers, ServerEvent, SocketUserData, UnackedEvent, Unsigned},
 };
 
 /// Extension trait for the Depot.
@@ -36,9 +39,15 @@ pub trait DepotExt {
     /// Returns the database connection
     fn db_conn(&self) -> Arc<DatabaseConnection>;
     /// Returns the server configuration
-    fn config(&self) -> &Config;
+    fn config(&self) -> Arc<Config>;
     /// Retutns the nonce cache
     fn nonce_cache(&self) -> Arc<NonceCache>;
+    /// Returns the cache of the derived keys
+    fn secret_cache(&self) -> Arc<SecretCache>;
+    /// Returns the federation client
+    fn federation(&self) -> Arc<Federation>;
+    /// Returns the registration status
+    fn registration(&self) -> Arc<Registration>;
 }
 
 /// Extension trait for online websocket users
@@ -49,20 +58,50 @@ pub trait OnlineUsersExt {
     /// Remove user from online users
     async fn remove_user(&self, conn_id: &Uuid);
 
-    /// Ping all online users
-    async fn ping_all(&self);
+    /// Ping the connection, returns `false` if the connection is not online
+    async fn ping(&self, conn_id: &Uuid) -> bool;
 
     /// Update user pong at time
     async fn update_pong(&self, conn_id: &Uuid);
 
-    /// Disconnect inactive users (who not respond for the ping event)
-    async fn disconnect_inactive_users(&self);
+    /// Returns true if the connection didn't respond to the last ping
+    async fn is_inactive(&self, conn_id: &Uuid) -> bool;
 
-    /// Returns the connection id of the user, if it is online
-    async fn is_online(&self, public_key: &PublicKey) -> Option<Uuid>;
+    /// Returns true if the user has at least one connection
+    async fn is_online(&self, public_key: &PublicKey) -> bool;
 
-    /// Send an event to user by connection id
-    async fn send(&self, conn_id: &Uuid, event: ServerEvent<Unsigned>);
+    /// Close all the user connections
+    async fn disconnect_user(&self, public_key: &PublicKey);
+
+    /// Returns the number of the online connections
+    async fn connections_count(&self) -> usize;
+
+    /// Returns the number of the online users, a user may have multiple
+    /// connections
+    async fn users_count(&self) -> usize;
+
+    /// Send an event to all the user connections, returns `false` if the user
+    /// is offline or one of their connections can't keep up with the events
+    async fn send_to_user(&self, public_key: &PublicKey, event: &ServerEvent<Unsigned>) -> bool;
+
+    /// Send a stored event to all the user connections, each connection keeps
+    /// it until the user acknowledges it. If a connection can't receive the
+    /// event, the event is not acknowledged by any connection, so it stays in
+    /// the database and it's delivered in the next connection
+    async fn send_stored_to_user(
+        &self,
+        public_key: &PublicKey,
+        event: &ServerEvent<Unsigned>,
+        stored_event: UnackedEvent,
+    );
+
+    /// Keep the stored event in the connection until the user acknowledges it
+    async fn add_unacked(&self, conn_id: &Uuid, event_id: Uuid, stored_event: UnackedEvent);
+
+    /// Acknowledge the event in the connection, returns the stored event if it
+    /// can be deleted from the database, the event is not deleted while
+    /// another connection of the user is waiting for its acknowledgement
+    async fn ack_event(&self, conn_id: &Uuid, event_id: &Uuid) -> Option<UnackedEvent>;
 }
 
 impl DepotExt for Depot {
@@ -73,8 +112,8 @@ impl DepotExt for Depot {
         )
     }
 
-    fn config(&self) -> &Config {
-        self.obtain::<Arc<Config>>().expect("Config not found")
+    fn config(&self) -> Arc<Config> {
+        Arc::clone(self.obtain::<Arc<Config>>().expect("Config not found"))
     }
 
     fn nonce_cache(&self) -> Arc<NonceCache> {
@@ -83,59 +122,179 @@ impl DepotExt for Depot {
                 .expect("Nonce cache not found"),
         )
     }
+
+    fn secret_cache(&self) -> Arc<SecretCache> {
+        Arc::clone(
+            self.obtain::<Arc<SecretCache>>()
+                .expect("Secret cache not found"),
+        )
+    }
+
+    fn federation(&self) -> Arc<Federation> {
+        Arc::clone(
+            self.obtain::<Arc<Federation>>()
+                .expect("Federation client not found"),
+        )
+    }
+
+    fn registration(&self) -> Arc<Registration> {
+        Arc::clone(
+            self.obtain::<Arc<Registration>>()
+                .expect("Registration status not found"),
+        )
+    }
 }
 
 impl OnlineUsersExt for OnlineUsers {
     async fn add_user(&self, conn_id: &Uuid, data: SocketUserData) {
-        self.write().await.insert(*conn_id, data);
+        let public_key = data.public_key;
+        self.connections.insert(*conn_id, data);
+        self.by_public_key
+            .entry(public_key)
+            .or_default()
+            .insert(*conn_id);
     }
 
     async fn remove_user(&self, conn_id: &Uuid) {
-        self.write().await.remove(conn_id);
+        let Some((_, user)) = self.connections.remove(conn_id) else {
+            return;
+        };
+        if let Entry::Occupied(mut user_connections) = self.by_public_key.entry(user.public_key) {
+            user_connections.get_mut().remove(conn_id);
+            if user_connections.get().is_empty() {
+                user_connections.remove();
+            }
+        }
     }
 
-    async fn ping_all(&self) {
-        let now = Utc::now();
-        self.write().await.par_iter_mut().for_each(|(_, u)| {
-            u.pinged_at = now;
-            let _ = u.sender.unbounded_send(Ok(ServerEvent::ping()
-                .sign(&u.shared_secret)
-                .as_ref()
-                .into()));
-        });
+    async fn ping(&self, conn_id: &Uuid) -> bool {
+        let Some(mut user) = self.connections.get_mut(conn_id) else {
+            return false;
+        };
+        user.pinged_at = Utc::now();
+        user.try_send(conn_id, ServerEvent::ping());
+        true
     }
 
     async fn update_pong(&self, conn_id: &Uuid) {
-        if let Some(user) = self.write().await.get_mut(conn_id) {
+        if let Some(mut user) = self.connections.get_mut(conn_id) {
             user.ponged_at = Utc::now()
         }
     }
 
-    async fn disconnect_inactive_users(&self) {
-        self.write().await.retain(|_, u| {
-            // if we send ping and the client doesn't send pong
-            if u.pinged_at > u.ponged_at {
-                log::info!("Disconnected from {}, inactive", u.public_key);
-                u.sender.close_channel();
-                return false;
+    async fn is_inactive(&self, conn_id: &Uuid) -> bool {
+        // if we send ping and the client doesn't send pong
+        self.connections
+            .get(conn_id)
+            .is_some_and(|user| user.pinged_at > user.ponged_at)
+    }
+
+    async fn is_online(&self, public_key: &PublicKey) -> bool {
+        self.by_public_key.contains_key(public_key)
+    }
+
+    async fn disconnect_user(&self, public_key: &PublicKey) {
+        let Some(user_connections) = self
+            .by_public_key
+            .get(public_key)
+            .map(|connections| connections.clone())
+        else {
+            return;
+        };
+        for conn_id in user_connections {
+            if let Some(mut user) = self.connections.get_mut(&conn_id) {
+                user.sender.close_channel();
             }
-            true
-        });
+        }
+    }
+
+    async fn connections_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    async fn users_count(&self) -> usize {
+        self.by_public_key.len()
+    }
+
+    async fn send_to_user(&self, public_key: &PublicKey, event: &ServerEvent<Unsigned>) -> bool {
+        // Clone the connections ids, to not lock the two maps at the same time
+        let Some(user_connections) = self
+            .by_public_key
+            .get(public_key)
+            .map(|connections| connections.clone())
+        else {
+            return false;
+        };
+
+        let mut is_sent = true;
+        for conn_id in user_connections {
+            if let Some(mut user) = self.connections.get_mut(&conn_id) {
+                is_sent &= user.try_send(&conn_id, event.clone());
+            }
+        }
+        is_sent
     }
 
-    async fn is_online(&self, public_key: &PublicKey) -> Option<Uuid> {
-        self.read()
-            .await
-            .iter()
-            .find(|(_, u)| &u.public_key == public_key)
-            .map(|(c, _)| *c)
+    async fn send_stored_to_user(
+        &self,
+        public_key: &PublicKey,
+        event: &ServerEvent<Unsigned>,
+        stored_event: UnackedEvent,
+    ) {
+        let Some(user_connections) = self
+            .by_public_key
+            .get(public_key)
+            .map(|connections| connections.clone())
+        else {
+            return;
+        };
+
+        let mut is_sent = true;
+        let mut received_connections = Vec::with_capacity(user_connections.len());
+        for conn_id in user_connections {
+            if let Some(mut user) = self.connections.get_mut(&conn_id) {
+                if user.try_send(&conn_id, event.clone()) {
+                    received_connections.push(conn_id);
+                } else {
+                    is_sent = false;
+                }
+            }
+        }
+        if !is_sent {
+            return;
+        }
+        for conn_id in received_connections {
+            self.add_unacked(&conn_id, *event.id(), stored_event.clone())
+                .await;
+        }
     }
 
-    async fn send(&self, conn_id: &Uuid, event: ServerEvent<Unsigned>) {
-        if let Some(user) = self.read().await.get(conn_id) {
-            let _ = user
-                .sender
-                .unbounded_send(Ok(event.sign(&user.shared_secret).as_ref().into()));
+    async fn add_unacked(&self, conn_id: &Uuid, event_id: Uuid, stored_event: UnackedEvent) {
+        if let Some(mut user) = self.connections.get_mut(conn_id) {
+            user.unacked_events.insert(event_id, stored_event);
         }
     }
+
+    async fn ack_event(&self, conn_id: &Uuid, event_id: &Uuid) -> Option<UnackedEvent> {
+        let (public_key, stored_event) = {
+            let mut user = self.connections.get_mut(conn_id)?;
+            (user.public_key, user.unacked_events.remove(event_id)?)
+        };
+        // Clone the connections ids, to not lock the two maps at the same time
+        let user_connections = self
+            .by_public_key
+            .get(&public_key)
+            .map(|connections| connections.clone())
+            .unwrap_or_default();
+
+        let is_waiting_elsewhere = user_connections.iter().any(|other_conn_id| {
+            other_conn_id != conn_id
+                && self.connections.get(other_conn_id).is_some_and(|user| {
+                    user.unacked_events
+                        .values()
+                        .any(|unacked| unacked == &stored_event)
+                })
+        });
+        (!is_waiting_elsewhere).then_some(stored_event)
+    }
 }
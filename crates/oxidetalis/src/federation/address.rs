@@ -0,0 +1,97 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! User address, the user public key and their homeserver.
+
+use std::{fmt, str::FromStr};
+
+use oxidetalis_core::types::{FederatedUserId, PublicKey};
+use serde::{de::Error as DeError, Deserialize, Serialize};
+
+/// User address, in the form `base58key@server`, the server part is omitted
+/// for the users of this homeserver. The addresses with a server are parsed as
+/// [`FederatedUserId`]
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct UserAddress {
+    /// The user public key
+    pub public_key: PublicKey,
+    /// The user homeserver, `None` if the user is a local user
+    pub server:     Option<String>,
+}
+
+impl UserAddress {
+    /// Creates new [`UserAddress`]
+    pub const fn new(public_key: PublicKey, server: Option<String>) -> Self {
+        Self { public_key, server }
+    }
+}
+
+impl From<PublicKey> for UserAddress {
+    fn from(public_key: PublicKey) -> Self {
+        Self::new(public_key, None)
+    }
+}
+
+impl From<FederatedUserId> for UserAddress {
+    fn from(user_id: FederatedUserId) -> Self {
+        let server = user_id.server();
+        Self::new(user_id.public_key, Some(server))
+    }
+}
+
+impl FromStr for UserAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains('@') {
+            FederatedUserId::from_str(s)
+                .map(Into::into)
+                .map_err(|err| err.to_string())
+        } else {
+            PublicKey::from_str(s)
+                .map(Into::into)
+                .map_err(|err| err.to_string())
+        }
+    }
+}
+
+impl fmt::Display for UserAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(server) = &self.server {
+            write!(f, "{}@{server}", self.public_key)
+        } else {
+            write!(f, "{}", self.public_key)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for UserAddress {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        Self::from_str(&String::deserialize(deserializer)?).map_err(DeError::custom)
+    }
+}
+
+impl Serialize for UserAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
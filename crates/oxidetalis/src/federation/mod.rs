@@ -0,0 +1,423 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Server-to-server federation, delivers the events of the local users to the
+//! remote homeservers over signed HTTP requests.
+
+use std::{
+    collections::HashSet,
+    fmt,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dashmap::DashMap;
+use oxidetalis_config::Config;
+use oxidetalis_core::{
+    canonical::CanonicalRequest,
+    cipher::K256Secret,
+    types::PublicKey,
+    PUBLIC_KEY_HEADER,
+    SERVER_NAME_HEADER,
+    SIGNATURE_HEADER,
+};
+use reqwest::{header::CONTENT_TYPE, redirect::Policy as RedirectPolicy, StatusCode};
+use salvo::Request;
+use tokio::sync::Notify;
+
+mod address;
+mod outbox;
+mod resolver;
+
+pub use address::*;
+pub use outbox::*;
+use resolver::{is_public_ip, FederationResolver};
+
+use crate::{
+    schemas::{FederationEvent, SignedDiscoveryDocument},
+    websocket::errors::WsError,
+};
+
+/// Timeout of the requests to the remote homeservers
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+/// Seconds before retrying a failed discovery of a remote homeserver
+const FAILED_LOOKUP_BACKOFF_SECS: u64 = 60;
+/// Minimum seconds between two discoveries of the same remote homeserver key
+const KEY_REFRESH_INTERVAL_SECS: u64 = 60;
+/// Number of the failed discoveries kept before dropping the expired ones
+const MAX_FAILED_LOOKUPS: usize = 10_000;
+
+/// Federation errors
+#[derive(Debug, thiserror::Error)]
+pub enum FederationError {
+    /// Couldn't reach the remote homeserver
+    #[error("Federation request error: {0}")]
+    Request(#[from] reqwest::Error),
+    /// The remote homeserver rejected the event
+    #[error("The remote homeserver rejected the event with `{0}`")]
+    Rejected(StatusCode),
+    /// The discovery document of the remote homeserver is invalid, it's not
+    /// signed by the homeserver key or it's for another homeserver
+    #[error("Invalid discovery document of `{0}`")]
+    InvalidDiscovery(String),
+    /// The remote homeserver is not allowed to federate with this homeserver
+    #[error("The homeserver `{0}` is not allowed to federate with this homeserver")]
+    NotAllowed(String),
+    /// The remote homeserver is on a loopback, private or link-local address
+    #[error("The homeserver `{0}` is on a private address")]
+    PrivateAddress(String),
+    /// The discovery of the remote homeserver failed recently, it will not be
+    /// retried until the backoff is over
+    #[error("The discovery of `{0}` failed recently, try again later")]
+    LookupBackoff(String),
+}
+
+impl FederationError {
+    /// Returns true if the error is temporary, the remote homeserver is
+    /// unreachable or unavailable for now, so the event can be retried later
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::Request(err) => !err.is_builder(),
+            Self::Rejected(status) => {
+                status.is_server_error()
+                    || matches!(
+                        *status,
+                        StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS
+                    )
+            }
+            Self::LookupBackoff(_) => true,
+            Self::InvalidDiscovery(_) | Self::NotAllowed(_) | Self::PrivateAddress(_) => false,
+        }
+    }
+
+    /// Returns the websocket error of the federation error, `not_found` is
+    /// returned if the remote homeserver can't find what the event refers to
+    pub fn ws_error(&self, not_found: WsError) -> WsError {
+        log::error!("{self}");
+        match self {
+            Self::Rejected(StatusCode::NOT_FOUND) => not_found,
+            Self::Rejected(StatusCode::FORBIDDEN) => WsError::RecipientBlacklist,
+            Self::Rejected(StatusCode::CONFLICT) => WsError::AlreadyInRecipientWhitelist,
+            Self::NotAllowed(_) | Self::PrivateAddress(_) => WsError::ServerNotAllowed,
+            _ => WsError::FederationFailed,
+        }
+    }
+}
+
+/// Federation client, sends the events to the remote homeservers
+pub struct Federation {
+    /// HTTP client
+    http:                reqwest::Client,
+    /// The name of this homeserver
+    server_name:         String,
+    /// The keypair of this homeserver
+    private_key:         K256Secret,
+    /// Cached public keys of the remote homeservers, with the time they were
+    /// fetched at
+    servers_keys:        DashMap<String, (PublicKey, Instant)>,
+    /// The remote homeservers that their discovery failed, with the time it
+    /// failed at
+    failed_lookups:      DashMap<String, Instant>,
+    /// Whether the homeservers on the private addresses are allowed
+    allow_private:       bool,
+    /// Whether the loopback homeservers are reached over plain HTTP
+    plain_http_loopback: bool,
+    /// The only homeservers that are allowed to federate, all the homeservers
+    /// are allowed if it's empty
+    allowed_servers:     HashSet<String>,
+    /// The homeservers that are not allowed to federate
+    denied_servers:      HashSet<String>,
+    /// The public keys of the only homeservers that are allowed to federate,
+    /// all the homeservers are allowed if it's empty
+    allowed_public_keys: HashSet<PublicKey>,
+    /// The public keys of the homeservers that are not allowed to federate
+    denied_public_keys:  HashSet<PublicKey>,
+    /// Wakes the outbox worker up when an event is added to the outbox
+    outbox_notify:       Notify,
+}
+
+impl fmt::Debug for Federation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Federation")
+            .field("server_name", &self.server_name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Federation {
+    /// Creates new [`Federation`]
+    pub fn new(config: &Config) -> Self {
+        Self {
+            http:                reqwest::Client::builder()
+                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+                .redirect(RedirectPolicy::none())
+                .dns_resolver(Arc::new(FederationResolver::new(
+                    config.federation.allow_private_servers,
+                )))
+                .build()
+                .expect("The TLS backend and the resolver are available"),
+            server_name:         config.server.server_name.to_ascii_lowercase(),
+            private_key:         config.server.private_key.clone(),
+            servers_keys:        DashMap::new(),
+            failed_lookups:      DashMap::new(),
+            allow_private:       config.federation.allow_private_servers,
+            plain_http_loopback: config.federation.dev_plain_http_loopback,
+            allowed_servers:     lowercase_servers(&config.federation.allowed_servers),
+            denied_servers:      lowercase_servers(&config.federation.denied_servers),
+            allowed_public_keys: config
+                .federation
+                .allowed_public_keys
+                .iter()
+                .copied()
+                .collect(),
+            denied_public_keys:  config
+                .federation
+                .denied_public_keys
+                .iter()
+                .copied()
+                .collect(),
+            outbox_notify:       Notify::new(),
+        }
+    }
+
+    /// Wake the outbox worker up to deliver the new outbox events, without
+    /// waiting for its next check
+    pub fn wake_outbox(&self) {
+        self.outbox_notify.notify_one();
+    }
+
+    /// Returns true if the server is this homeserver
+    pub fn is_local_server(&self, server: &str) -> bool {
+        server == self.server_name
+    }
+
+    /// Returns true if the user is a user of this homeserver
+    pub fn is_local(&self, user: &UserAddress) -> bool {
+        user.server
+            .as_deref()
+            .map_or(true, |server| self.is_local_server(server))
+    }
+
+    /// Returns true if the homeserver is allowed to federate with this
+    /// homeserver, by its name
+    pub fn is_allowed_server(&self, server: &str) -> bool {
+        !self.denied_servers.contains(server)
+            && (self.allowed_servers.is_empty() || self.allowed_servers.contains(server))
+    }
+
+    /// Returns true if the homeserver is allowed to federate with this
+    /// homeserver, by its public key
+    pub fn is_allowed_public_key(&self, public_key: &PublicKey) -> bool {
+        !self.denied_public_keys.contains(public_key)
+            && (self.allowed_public_keys.is_empty()
+                || self.allowed_public_keys.contains(public_key))
+    }
+
+    /// Returns the public key of the remote homeserver, the cached key is
+    /// returned unless `refresh` is true. The key is not refreshed more than
+    /// once per [`KEY_REFRESH_INTERVAL_SECS`], and a failed discovery is not
+    /// retried before [`FAILED_LOOKUP_BACKOFF_SECS`].
+    ///
+    /// Returns [`FederationError::NotAllowed`] if the homeserver name or its
+    /// public key is not allowed to federate with this homeserver.
+    pub async fn server_public_key(
+        &self,
+        server: &str,
+        refresh: bool,
+    ) -> Result<PublicKey, FederationError> {
+        if !self.is_allowed_server(server) {
+            return Err(FederationError::NotAllowed(server.to_owned()));
+        }
+        if let Some(cached) = self.servers_keys.get(server) {
+            let (public_key, fetched_at) = *cached;
+            if !refresh || fetched_at.elapsed() < Duration::from_secs(KEY_REFRESH_INTERVAL_SECS) {
+                return self.check_public_key(server, public_key);
+            }
+        }
+        if self.failed_lookups.get(server).is_some_and(|failed_at| {
+            failed_at.elapsed() < Duration::from_secs(FAILED_LOOKUP_BACKOFF_SECS)
+        }) {
+            return Err(FederationError::LookupBackoff(server.to_owned()));
+        }
+
+        let public_key = match self.discover(server).await {
+            Ok(public_key) => public_key,
+            Err(err) => {
+                self.add_failed_lookup(server);
+                return Err(err);
+            }
+        };
+        self.failed_lookups.remove(server);
+        self.servers_keys
+            .insert(server.to_owned(), (public_key, Instant::now()));
+        self.check_public_key(server, public_key)
+    }
+
+    /// Fetch the discovery document of the remote homeserver, and returns its
+    /// public key
+    async fn discover(&self, server: &str) -> Result<PublicKey, FederationError> {
+        let discovery = self
+            .http
+            .get(format!(
+                "{}/.well-known/otmp",
+                self.remote_server_url(server)?
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<SignedDiscoveryDocument>()
+            .await?;
+        // The signature proves that the homeserver owns the key in the document
+        if discovery.document.server_name.to_ascii_lowercase() != server
+            || !discovery
+                .signature
+                .verify_ecdsa(&discovery.document.data(), &discovery.document.public_key)
+        {
+            return Err(FederationError::InvalidDiscovery(server.to_owned()));
+        }
+        Ok(discovery.document.public_key)
+    }
+
+    /// Remember the failed discovery of the remote homeserver, the expired
+    /// failures are dropped when there are too many of them
+    fn add_failed_lookup(&self, server: &str) {
+        if self.failed_lookups.len() >= MAX_FAILED_LOOKUPS {
+            self.failed_lookups.retain(|_, failed_at| {
+                failed_at.elapsed() < Duration::from_secs(FAILED_LOOKUP_BACKOFF_SECS)
+            });
+        }
+        self.failed_lookups
+            .insert(server.to_owned(), Instant::now());
+    }
+
+    /// Returns the base url of the homeserver, the loopback homeservers are
+    /// reached over plain HTTP if it's enabled, to be able to test the
+    /// federation locally
+    pub fn server_url(&self, server: &str) -> String {
+        let host = server_host(server);
+        if self.plain_http_loopback
+            && (host == "localhost" || host.parse::<IpAddr>().is_ok_and(|ip| ip.is_loopback()))
+        {
+            format!("http://{server}")
+        } else {
+            format!("https://{server}")
+        }
+    }
+
+    /// Returns the base url of the remote homeserver, the homeservers on the
+    /// private addresses are rejected unless they are allowed. The names are
+    /// checked when they are resolved.
+    fn remote_server_url(&self, server: &str) -> Result<String, FederationError> {
+        let host = server_host(server);
+        let is_private = host.parse::<IpAddr>().map_or_else(
+            |_| host.eq_ignore_ascii_case("localhost"),
+            |ip| !is_public_ip(&ip),
+        );
+        if is_private && !self.allow_private {
+            return Err(FederationError::PrivateAddress(server.to_owned()));
+        }
+        Ok(self.server_url(server))
+    }
+
+    /// Returns the public key if the homeserver is allowed by its public key
+    fn check_public_key(
+        &self,
+        server: &str,
+        public_key: PublicKey,
+    ) -> Result<PublicKey, FederationError> {
+        if self.is_allowed_public_key(&public_key) {
+            Ok(public_key)
+        } else {
+            Err(FederationError::NotAllowed(server.to_owned()))
+        }
+    }
+
+    /// Send the event to the remote homeserver
+    pub async fn send_event(
+        &self,
+        server: &str,
+        event: &FederationEvent,
+    ) -> Result<(), FederationError> {
+        let body = serde_json::to_string(event).expect("Can't fail");
+        let public_key = self.private_key.pubkey().to_string();
+        let mut status = StatusCode::UNAUTHORIZED;
+        // If the remote homeserver changed its key, the cached key will not
+        // work, so retry once with a fresh key
+        for refresh in [false, true] {
+            let server_public_key = self.server_public_key(server, refresh).await?;
+            let canonical = CanonicalRequest::new("POST", "/federation/events", body.as_bytes())
+                .with_header(PUBLIC_KEY_HEADER, &public_key)
+                .with_header(SERVER_NAME_HEADER, &self.server_name);
+            status = self
+                .http
+                .post(format!(
+                    "{}/federation/events",
+                    self.remote_server_url(server)?
+                ))
+                .header(CONTENT_TYPE, "application/json")
+                .header(PUBLIC_KEY_HEADER, &public_key)
+                .header(
+                    SIGNATURE_HEADER,
+                    self.private_key
+                        .sign(&canonical.to_bytes(), &server_public_key)
+                        .to_string(),
+                )
+                .header(SERVER_NAME_HEADER, &self.server_name)
+                .body(body.clone())
+                .send()
+                .await?
+                .status();
+            if status != StatusCode::UNAUTHORIZED {
+                break;
+            }
+        }
+
+        if status.is_success() {
+            Ok(())
+        } else {
+            Err(FederationError::Rejected(status))
+        }
+    }
+}
+
+/// Returns the lowercased homeservers names
+fn lowercase_servers(servers: &[String]) -> HashSet<String> {
+    servers
+        .iter()
+        .map(|server| server.to_ascii_lowercase())
+        .collect()
+}
+
+/// Returns the name of the homeserver that sent the request
+pub fn sender_server(req: &Request) -> Option<String> {
+    req.headers()
+        .get(SERVER_NAME_HEADER)
+        .and_then(|server| server.to_str().ok())
+        .filter(|server| !server.is_empty())
+        .map(str::to_ascii_lowercase)
+}
+
+/// Returns the host of the homeserver name, without the port and the IPv6
+/// brackets
+fn server_host(server: &str) -> &str {
+    server
+        .rsplit_once(':')
+        .filter(|(host, _)| !host.contains(':') || host.ends_with(']'))
+        .map_or(server, |(host, _)| host)
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+}
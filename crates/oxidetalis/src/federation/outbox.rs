@@ -0,0 +1,215 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Federation outbox, the events to the remote homeservers are delivered from
+//! the outbox and retried with an exponential backoff until the deadline.
+//!
+//! The events to the same homeserver are delivered in order, the oldest event
+//! of the homeserver blocks the newer ones until it's delivered or dropped, so
+//! the backoff is per homeserver.
+
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, TimeDelta, Utc};
+use oxidetalis_config::Config;
+use oxidetalis_entities::prelude::*;
+use sea_orm::DatabaseConnection;
+use tokio::time::{interval, MissedTickBehavior};
+
+use super::Federation;
+use crate::{
+    database::{FederationOutboxExt, OutChatRequestsExt, QueuedEventsExt},
+    extensions::OnlineUsersExt,
+    schemas::FederationEvent,
+    websocket::{errors::WsError, ServerEvent, UnackedEvent, ONLINE_USERS},
+};
+
+/// Seconds between the outbox checks
+const POLL_INTERVAL_SECS: u64 = 5;
+/// Maximum number of homeservers, and of events to each one, delivered in a
+/// single check
+const BATCH_SIZE: u64 = 100;
+/// Seconds before the first retry, doubled after each failed attempt
+const BASE_BACKOFF_SECS: i64 = 10;
+/// Maximum seconds between two attempts
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+
+/// Deliver the due outbox events forever, the events are retried until they
+/// are delivered, rejected by the remote homeserver or their deadline is
+/// reached. The sender is notified if the event is not delivered.
+pub async fn outbox_worker(
+    db: Arc<DatabaseConnection>,
+    config: Arc<Config>,
+    federation: Arc<Federation>,
+) {
+    let mut interval = interval(Duration::from_secs(POLL_INTERVAL_SECS));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            () = federation.outbox_notify.notified() => {}
+        }
+        let servers = match db.due_federation_servers(BATCH_SIZE).await {
+            Ok(servers) => servers,
+            Err(err) => {
+                log::error!("Couldn't get the federation outbox servers: {err}");
+                continue;
+            }
+        };
+        for server in servers {
+            deliver_server_events(&db, &config, &federation, &server).await;
+        }
+    }
+}
+
+/// Deliver the outbox events to the server in order, stops at the first event
+/// that is rescheduled
+async fn deliver_server_events(
+    db: &DatabaseConnection,
+    config: &Config,
+    federation: &Federation,
+    server: &str,
+) {
+    let events = match db.federation_events_to(server, BATCH_SIZE).await {
+        Ok(events) => events,
+        Err(err) => {
+            log::error!("Couldn't get the federation outbox events to `{server}`: {err}");
+            return;
+        }
+    };
+    for (outbox_event, sender) in events {
+        if !deliver_event(db, config, federation, outbox_event, sender).await {
+            break;
+        }
+    }
+}
+
+/// Try to deliver the outbox event, and reschedule it if it's still possible.
+///
+/// Returns `false` if the event is rescheduled, so the newer events to the
+/// same server have to wait for it
+async fn deliver_event(
+    db: &DatabaseConnection,
+    config: &Config,
+    federation: &Federation,
+    outbox_event: FederationOutboxModel,
+    sender: Option<UserModel>,
+) -> bool {
+    let event = match serde_json::from_str::<FederationEvent>(&outbox_event.payload) {
+        Ok(event) => event,
+        Err(err) => {
+            log::error!("Invalid federation outbox event, it will be dropped: {err}");
+            remove_event(db, outbox_event).await;
+            return true;
+        }
+    };
+    // The outbox events are removed with their senders, but just in case
+    let Some(sender) = sender else {
+        remove_event(db, outbox_event).await;
+        return true;
+    };
+
+    let error = match federation.send_event(&outbox_event.server, &event).await {
+        Ok(()) => {
+            remove_event(db, outbox_event).await;
+            return true;
+        }
+        Err(err) if err.is_transient() => {
+            let next_retry_at = Utc::now() + backoff(outbox_event.attempts);
+            if next_retry_at <= deadline(&outbox_event, config.federation.outbox_deadline_secs) {
+                log::warn!(
+                    "Federation event to `{}` will be retried at {next_retry_at}: {err}",
+                    outbox_event.server
+                );
+                if let Err(err) = db
+                    .reschedule_federation_event(outbox_event, next_retry_at)
+                    .await
+                {
+                    log::error!("Couldn't reschedule the federation event: {err}");
+                }
+                return false;
+            }
+            log::error!(
+                "Giving up on the federation event to `{}`, the deadline is reached",
+                outbox_event.server
+            );
+            WsError::FederationFailed
+        }
+        Err(err) => {
+            err.ws_error(match event {
+                FederationEvent::ChatRequest { .. } => WsError::UserNotFound,
+                FederationEvent::ChatRequestResponse { .. } => WsError::NoChatRequestFromRecipient,
+                FederationEvent::CancelChatRequest { .. } => WsError::NoChatRequestToRecipient,
+            })
+        }
+    };
+
+    // The chat request is not delivered, so the sender can send it again
+    if let FederationEvent::ChatRequest { to, .. } = &event {
+        if let Err(err) = db.remove_out_chat_request(&sender, to).await {
+            log::error!("Couldn't remove the undelivered chat request: {err}");
+        }
+    }
+    let request_id = outbox_event.request_id.clone();
+    remove_event(db, outbox_event).await;
+
+    let notice = ServerEvent::from(error).with_request_id(request_id);
+    match db
+        .queue_event(&sender, notice.event(), &config.offline_queue)
+        .await
+    {
+        Ok(queued_event) => {
+            ONLINE_USERS
+                .send_stored_to_user(
+                    &sender.public_key,
+                    &notice,
+                    UnackedEvent::QueuedEvent(queued_event),
+                )
+                .await;
+        }
+        Err(err) => {
+            log::error!("Couldn't notify the sender about the undelivered event: {err}");
+        }
+    }
+    true
+}
+
+/// Remove the event from the outbox
+async fn remove_event(db: &DatabaseConnection, outbox_event: FederationOutboxModel) {
+    if let Err(err) = outbox_event.delete(db).await {
+        log::error!("Couldn't remove the federation outbox event: {err}");
+    }
+}
+
+/// Returns the delay before the next attempt, the delay is doubled after each
+/// failed attempt
+fn backoff(attempts: i32) -> TimeDelta {
+    let factor = 2_i64.saturating_pow(u32::try_from(attempts).unwrap_or_default());
+    TimeDelta::seconds(
+        BASE_BACKOFF_SECS
+            .saturating_mul(factor)
+            .min(MAX_BACKOFF_SECS),
+    )
+}
+
+/// Returns the time that the event will be dropped at, if it's not delivered
+fn deadline(outbox_event: &FederationOutboxModel, deadline_secs: u64) -> DateTime<Utc> {
+    i64::try_from(deadline_secs)
+        .ok()
+        .and_then(TimeDelta::try_seconds)
+        .and_then(|deadline| outbox_event.created_at.checked_add_signed(deadline))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
@@ -0,0 +1,88 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Federation DNS resolver, keeps the federation requests away from the
+//! internal network of this homeserver.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tokio::net::lookup_host;
+
+/// Resolver of the remote homeservers names, the loopback, private and
+/// link-local addresses are dropped unless they are allowed
+pub struct FederationResolver {
+    /// Whether the loopback, private and link-local addresses are allowed
+    allow_private: bool,
+}
+
+impl FederationResolver {
+    /// Creates new [`FederationResolver`]
+    pub const fn new(allow_private: bool) -> Self {
+        Self { allow_private }
+    }
+}
+
+impl Resolve for FederationResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private = self.allow_private;
+        Box::pin(async move {
+            let addrs = lookup_host((name.as_str(), 0))
+                .await?
+                .filter(|addr| allow_private || is_public_ip(&addr.ip()))
+                .collect::<Vec<SocketAddr>>();
+            if addrs.is_empty() {
+                return Err(format!("`{}` has no public address", name.as_str()).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Returns true if the address is reachable over the internet, it's not a
+/// loopback, private, link-local or unspecified address
+pub fn is_public_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => is_public_ipv4(ip),
+        IpAddr::V6(ip) => {
+            ip.to_ipv4_mapped()
+                .map_or_else(|| is_public_ipv6(ip), |ip| is_public_ipv4(&ip))
+        }
+    }
+}
+
+/// Returns true if the IPv4 address is reachable over the internet
+const fn is_public_ipv4(ip: &Ipv4Addr) -> bool {
+    let [first, second, ..] = ip.octets();
+    !(ip.is_loopback()
+        || ip.is_private()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        // Shared address space (100.64.0.0/10)
+        || (first == 100 && (second & 0b1100_0000) == 64))
+}
+
+/// Returns true if the IPv6 address is reachable over the internet
+const fn is_public_ipv6(ip: &Ipv6Addr) -> bool {
+    let first_segment = ip.segments()[0];
+    !(ip.is_loopback()
+        || ip.is_unspecified()
+        // Unique local addresses (fc00::/7)
+        || (first_segment & 0xfe00) == 0xfc00
+        // Link-local addresses (fe80::/10)
+        || (first_segment & 0xffc0) == 0xfe80)
+}
@@ -20,13 +20,16 @@
 /// Macro to return a [`ServerEvent`] with a [`WsError::InternalServerError`] if
 /// the result of an expression is an [`Err`].
 ///
+/// The returned event carries the client event id (the request id), so the
+/// client knows which request failed.
+///
 /// ## Example
 /// ```rust,ignore
-/// fn example() -> ServerEvent {
+/// fn example(request_id: Option<&str>) -> Option<ServerEvent> {
 ///    // some_function() returns a Result, if it's an Err, return an
-///    // ServerEvent::InternalServerError
-///    let result = try_ws!(some_function());
-///    ServerEvent::from(result)
+///    // ServerEvent::InternalServerError with the request id
+///    let result = try_ws!(Some request_id, some_function());
+///    Some(ServerEvent::from(result))
 /// }
 /// ```
 ///
@@ -35,7 +38,7 @@
 /// [`Err`]: std::result::Result::Err
 #[macro_export]
 macro_rules! try_ws {
-    (Some $result_expr:expr) => {
+    (Some $request_id:ident, $result_expr:expr) => {
         match $result_expr {
             Ok(val) => val,
             Err(err) => {
@@ -43,13 +46,54 @@ macro_rules! try_ws {
                 return Some(
                     $crate::websocket::ServerEvent::<$crate::websocket::Unsigned>::from(
                         $crate::websocket::errors::WsError::from(err),
-                    ),
+                    )
+                    .with_request_id($request_id.map(::std::borrow::ToOwned::to_owned)),
                 );
             }
         }
     };
 }
 
+/// Macro to define an enum with a `NAMES` constant, the names of its variants
+/// in the same order. The names are the variants identifiers, so they match the
+/// serde names of the `PascalCase` enums.
+///
+/// ## Example
+/// ```rust,ignore
+/// named_variants! {
+///     #[derive(Debug)]
+///     pub enum Event {
+///         First { data: u64 },
+///         Second,
+///     }
+/// }
+/// assert_eq!(Event::NAMES, &["First", "Second"]);
+/// ```
+#[macro_export]
+macro_rules! named_variants {
+    (
+        $(#[$meta:meta])*
+        $vis:vis enum $name:ident {
+            $(
+                $(#[$variant_meta:meta])*
+                $variant:ident $({ $($fields:tt)* })?
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis enum $name {
+            $(
+                $(#[$variant_meta])*
+                $variant $({ $($fields)* })?
+            ),+
+        }
+        impl $name {
+            #[doc = "The names of the variants, in their declaration order"]
+            pub const NAMES: &'static [&'static str] = &[$(stringify!($variant)),+];
+        }
+    };
+}
+
 /// Macro to create the `WsError` enum with the given error names and reasons.
 ///
 /// ## Example
@@ -27,12 +27,15 @@ use salvo::{conn::TcpListener, Listener, Server};
 mod database;
 mod errors;
 mod extensions;
+mod federation;
 mod macros;
 mod middlewares;
 mod nonce;
 mod parameters;
+mod registration;
 mod routes;
 mod schemas;
+mod secret_cache;
 mod utils;
 mod websocket;
 
@@ -0,0 +1,94 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Federation middleware, checks the identity of the remote homeservers.
+
+use oxidetalis_core::{types::PublicKey, SERVER_NAME_HEADER};
+use salvo::{handler, http::StatusCode, Depot, FlowCtrl, Request, Response, Writer};
+
+use crate::{extensions::DepotExt, federation};
+
+/// Middleware to check that the request is sent by the homeserver in the
+/// server name header, the request public key must be the public key of that
+/// homeserver.
+///
+/// This middleware must be used after the [`signature_check`] middleware.
+///
+/// [`signature_check`]: super::signature_check
+#[handler]
+pub async fn federation_check(
+    req: &mut Request,
+    res: &mut Response,
+    depot: &mut Depot,
+    ctrl: &mut FlowCtrl,
+    sender_public_key: PublicKey,
+) {
+    let Some(server) = federation::sender_server(req) else {
+        super::write_error(
+            res,
+            ctrl,
+            format!("Could not find {SERVER_NAME_HEADER} in headers"),
+            StatusCode::BAD_REQUEST,
+        );
+        return;
+    };
+    let federation = depot.federation();
+    if federation.is_local_server(&server) {
+        super::write_error(
+            res,
+            ctrl,
+            "A homeserver can't federate with itself".to_owned(),
+            StatusCode::FORBIDDEN,
+        );
+        return;
+    }
+
+    if !federation.is_allowed_server(&server)
+        || !federation.is_allowed_public_key(&sender_public_key)
+    {
+        super::write_error(
+            res,
+            ctrl,
+            format!("The homeserver `{server}` is not allowed to federate with this homeserver"),
+            StatusCode::FORBIDDEN,
+        );
+        return;
+    }
+
+    // The homeserver may changed its key, so refresh the cached key before
+    // rejecting the request
+    let is_valid_identity = match federation.server_public_key(&server, false).await {
+        Ok(public_key) if public_key == sender_public_key => true,
+        Ok(_) => {
+            federation
+                .server_public_key(&server, true)
+                .await
+                .is_ok_and(|public_key| public_key == sender_public_key)
+        }
+        Err(err) => {
+            log::error!("Couldn't get the identity of `{server}`: {err}");
+            false
+        }
+    };
+    if !is_valid_identity {
+        super::write_error(
+            res,
+            ctrl,
+            "Invalid homeserver identity".to_owned(),
+            StatusCode::UNAUTHORIZED,
+        );
+    }
+}
@@ -24,9 +24,13 @@ use salvo::{
     Response,
 };
 
+mod federation;
 mod signature;
+mod user;
 
+pub use federation::*;
 pub use signature::*;
+pub use user::*;
 
 use crate::{routes::write_json_body, schemas::MessageSchema};
 
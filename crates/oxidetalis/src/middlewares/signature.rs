@@ -16,23 +16,20 @@
 
 //! Request signature middleware.
 
-use oxidetalis_core::types::{PublicKey, Signature};
-use salvo::{
-    handler,
-    http::{Body, StatusCode},
-    Depot,
-    FlowCtrl,
-    Request,
-    Response,
-    Writer,
+use oxidetalis_core::{
+    canonical::CanonicalRequest,
+    types::{PublicKey, Signature, SignatureVersion},
 };
+use salvo::{handler, http::StatusCode, Depot, FlowCtrl, Request, Response, Writer};
 
 use crate::{extensions::DepotExt, utils};
 
 /// Middleware to check the signature of the request.
 ///
-/// If the signature is valid, the request will be passed to the next handler.
-/// Otherwise, a 401 Unauthorized response will be returned.
+/// The signature is of the canonical form of the request, see
+/// [`CanonicalRequest`]. If the signature is valid, the request will be passed
+/// to the next handler. Otherwise, a 401 Unauthorized response will be
+/// returned.
 #[handler]
 pub async fn signature_check(
     req: &mut Request,
@@ -46,25 +43,36 @@ pub async fn signature_check(
     let mut write_err =
         |message: &str, status_code| super::write_error(res, ctrl, message.to_owned(), status_code);
 
-    let data = if req.body().is_end_stream() {
-        format!("{}{}", req.method(), req.uri().path())
-    } else {
-        match req.parse_json::<serde_json::Value>().await {
-            Ok(j) => j.to_string(),
-            Err(err) => {
-                write_err(&err.to_string(), UNAUTHORIZED);
-                return;
-            }
+    let body = match req.payload().await {
+        Ok(body) => body.clone(),
+        Err(err) => {
+            write_err(&err.to_string(), UNAUTHORIZED);
+            return;
         }
     };
-
-    if !utils::is_valid_nonce(&signature, &depot.nonce_cache()).await
-        || !depot.config().server.private_key.verify(
-            data.as_bytes(),
-            &signature,
-            &sender_public_key,
+    let data = req
+        .headers()
+        .iter()
+        .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)))
+        .fold(
+            CanonicalRequest::new(req.method().as_str(), req.uri().path(), &body),
+            |canonical, (name, value)| canonical.with_header(name, value),
         )
-    {
+        .with_query(req.uri().query().unwrap_or_default())
+        .to_bytes();
+
+    let is_valid_signature = match signature.version() {
+        SignatureVersion::Hmac => {
+            // The key is cached, to avoid the ECDH on each request
+            let auth_key = depot
+                .secret_cache()
+                .auth_key(&depot.config().server.private_key, &sender_public_key)
+                .await;
+            signature.verify(&data, &auth_key)
+        }
+        SignatureVersion::Ecdsa => signature.verify_ecdsa(&data, &sender_public_key),
+    };
+    if !utils::is_valid_nonce(&signature, &depot.nonce_cache()).await || !is_valid_signature {
         write_err("Invalid signature", UNAUTHORIZED);
         return;
     }
@@ -0,0 +1,98 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! User middlewares, checks the permissions of the request sender.
+
+use oxidetalis_core::types::PublicKey;
+use salvo::{handler, http::StatusCode, Depot, FlowCtrl, Response, Writer};
+
+use crate::{database::UserTableExt, extensions::DepotExt, routes::ApiError};
+
+/// Middleware to check that the request sender is a homeserver admin.
+///
+/// This middleware must be used after the [`signature_check`] middleware.
+///
+/// [`signature_check`]: super::signature_check
+#[handler]
+pub async fn admin_check(
+    res: &mut Response,
+    depot: &mut Depot,
+    ctrl: &mut FlowCtrl,
+    sender_public_key: PublicKey,
+) {
+    match depot.db_conn().get_user_by_pubk(&sender_public_key).await {
+        Ok(Some(user)) if user.is_admin => {}
+        Ok(Some(_)) => {
+            super::write_error(
+                res,
+                ctrl,
+                ApiError::NotAdmin.to_string(),
+                ApiError::NotAdmin.status_code(),
+            );
+        }
+        Ok(None) => {
+            super::write_error(
+                res,
+                ctrl,
+                ApiError::NotRegisteredUser.to_string(),
+                ApiError::NotRegisteredUser.status_code(),
+            );
+        }
+        Err(err) => {
+            log::error!("{err}");
+            super::write_error(
+                res,
+                ctrl,
+                ApiError::Internal.to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    }
+}
+
+/// Middleware to reject the requests of the banned users.
+///
+/// This middleware must be used after the [`signature_check`] middleware.
+///
+/// [`signature_check`]: super::signature_check
+#[handler]
+pub async fn ban_check(
+    res: &mut Response,
+    depot: &mut Depot,
+    ctrl: &mut FlowCtrl,
+    sender_public_key: PublicKey,
+) {
+    match depot.db_conn().get_user_by_pubk(&sender_public_key).await {
+        Ok(Some(user)) if user.is_banned => {
+            super::write_error(
+                res,
+                ctrl,
+                ApiError::BannedUser.to_string(),
+                ApiError::BannedUser.status_code(),
+            );
+        }
+        Ok(_) => {}
+        Err(err) => {
+            log::error!("{err}");
+            super::write_error(
+                res,
+                ctrl,
+                ApiError::Internal.to_string(),
+                StatusCode::INTERNAL_SERVER_ERROR,
+            );
+        }
+    }
+}
@@ -0,0 +1,49 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Runtime registration status, the admins can open and close the
+//! registration without restarting the homeserver
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use oxidetalis_config::Register;
+
+/// The registration status of the homeserver, initialized from the
+/// registration config
+#[derive(Debug)]
+pub struct Registration {
+    /// Whether the registration is open or not
+    open: AtomicBool,
+}
+
+impl Registration {
+    /// Creates new [`Registration`] from the registration config
+    pub const fn new(config: &Register) -> Self {
+        Self {
+            open: AtomicBool::new(config.enable),
+        }
+    }
+
+    /// Returns true if the registration is open
+    pub fn is_open(&self) -> bool {
+        self.open.load(Ordering::Relaxed)
+    }
+
+    /// Open or close the registration
+    pub fn set_open(&self, open: bool) {
+        self.open.store(open, Ordering::Relaxed);
+    }
+}
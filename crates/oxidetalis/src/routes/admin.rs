@@ -0,0 +1,342 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! REST API endpoints for the homeserver admins
+
+use chrono::{TimeDelta, Utc};
+use oxidetalis_core::types::{PublicKey, Signature};
+use oxidetalis_entities::prelude::*;
+use salvo::{
+    http::StatusCode,
+    oapi::{
+        endpoint,
+        extract::{JsonBody, PathParam},
+    },
+    writing::Json,
+    Depot,
+    Response,
+    Router,
+    Writer,
+};
+use sea_orm::DatabaseConnection;
+
+use super::{ApiError, ApiResult};
+use crate::{
+    database::{InviteTokensExt, UserTableExt},
+    extensions::{DepotExt, OnlineUsersExt},
+    middlewares,
+    parameters::Pagination,
+    schemas::{
+        EmptySchema,
+        InviteToken,
+        MessageSchema,
+        NewInviteToken,
+        OnlineUsersCount,
+        RegistrationStatus,
+        UserInfo,
+    },
+    websocket::ONLINE_USERS,
+};
+
+/// (🔐) Get the registered users
+#[endpoint(
+    operation_id = "admin_users",
+    tags("Admin"),
+    responses(
+        (status_code = 200, description = "Returns the registered users", content_type = "application/json", body = Vec<UserInfo>),
+        (status_code = 400, description = "Invalid parameters or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn users(depot: &mut Depot, pagination: Pagination) -> ApiResult<Json<Vec<UserInfo>>> {
+    Ok(Json(
+        depot
+            .db_conn()
+            .users(pagination.page, pagination.page_size)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    ))
+}
+
+/// (🔐) Delete a user
+///
+/// Delete the user and all their data, the user connections will be closed.
+#[endpoint(
+    operation_id = "admin_delete_user",
+    tags("Admin"),
+    responses(
+        (status_code = 204, description = "User deleted"),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin, or deleting an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The user is not registered", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn delete_user(
+    depot: &mut Depot,
+    public_key: PathParam<PublicKey>,
+) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = moderated_user(&conn, &public_key).await?;
+    conn.delete_user(user).await?;
+    ONLINE_USERS.disconnect_user(&public_key).await;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Ban a user
+///
+/// Ban the user from the homeserver, the user connections will be closed and
+/// their requests will be rejected until they are unbanned.
+#[endpoint(
+    operation_id = "admin_ban_user",
+    tags("Admin"),
+    responses(
+        (status_code = 204, description = "User banned"),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin, or banning an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The user is not registered", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn ban_user(depot: &mut Depot, public_key: PathParam<PublicKey>) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = moderated_user(&conn, &public_key).await?;
+    conn.set_user_banned(user, true).await?;
+    ONLINE_USERS.disconnect_user(&public_key).await;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Unban a user
+#[endpoint(
+    operation_id = "admin_unban_user",
+    tags("Admin"),
+    responses(
+        (status_code = 204, description = "User unbanned"),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The user is not registered", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn unban_user(depot: &mut Depot, public_key: PathParam<PublicKey>) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = moderated_user(&conn, &public_key).await?;
+    conn.set_user_banned(user, false).await?;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Get the registration status
+#[endpoint(
+    operation_id = "admin_registration",
+    tags("Admin"),
+    responses(
+        (status_code = 200, description = "Returns the registration status", content_type = "application/json", body = RegistrationStatus),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn registration(depot: &mut Depot) -> Json<RegistrationStatus> {
+    Json(RegistrationStatus::new(depot.registration().is_open()))
+}
+
+/// (🔐) Open or close the registration
+///
+/// The registration status is kept until the homeserver restarts, then the
+/// registration config is used again.
+#[endpoint(
+    operation_id = "admin_set_registration",
+    tags("Admin"),
+    responses(
+        (status_code = 200, description = "Returns the new registration status", content_type = "application/json", body = RegistrationStatus),
+        (status_code = 400, description = "Invalid body or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn set_registration(
+    depot: &mut Depot,
+    status: JsonBody<RegistrationStatus>,
+) -> Json<RegistrationStatus> {
+    depot.registration().set_open(status.open);
+    Json(status.into_inner())
+}
+
+/// (🔐) Get the number of the online users
+#[endpoint(
+    operation_id = "admin_online",
+    tags("Admin"),
+    responses(
+        (status_code = 200, description = "Returns the number of the online users and connections", content_type = "application/json", body = OnlineUsersCount),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn online() -> Json<OnlineUsersCount> {
+    Json(OnlineUsersCount::new(
+        ONLINE_USERS.connections_count().await,
+        ONLINE_USERS.users_count().await,
+    ))
+}
+
+/// (🔐) Mint an invite token
+///
+/// The invite token lets its holders register while the registration is
+/// closed, until it expires or is used up.
+#[endpoint(
+    operation_id = "admin_mint_invite",
+    tags("Admin"),
+    responses(
+        (status_code = 201, description = "Returns the minted invite token", content_type = "application/json", body = InviteToken),
+        (status_code = 400, description = "Invalid body or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn mint_invite(
+    depot: &mut Depot,
+    res: &mut Response,
+    public_key: PublicKey,
+    new_token: JsonBody<NewInviteToken>,
+) -> ApiResult<Json<InviteToken>> {
+    let conn = depot.db_conn();
+    let admin = conn
+        .get_user_by_pubk(&public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    let max_uses = i32::try_from(new_token.max_uses.get()).unwrap_or(i32::MAX);
+    let expires_at = Utc::now() + TimeDelta::seconds(i64::from(new_token.expires_in_secs.get()));
+
+    let token = conn.mint_invite_token(&admin, max_uses, expires_at).await?;
+    res.status_code(StatusCode::CREATED);
+    Ok(Json(token.into()))
+}
+
+/// (🔐) Get the invite tokens
+///
+/// Returns the invite tokens, including the expired and used up ones, the
+/// newest first.
+#[endpoint(
+    operation_id = "admin_invites",
+    tags("Admin"),
+    responses(
+        (status_code = 200, description = "Returns the invite tokens", content_type = "application/json", body = Vec<InviteToken>),
+        (status_code = 400, description = "Invalid parameters or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn invites(depot: &mut Depot, pagination: Pagination) -> ApiResult<Json<Vec<InviteToken>>> {
+    Ok(Json(
+        depot
+            .db_conn()
+            .invite_tokens(pagination.page, pagination.page_size)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    ))
+}
+
+/// (🔐) Revoke an invite token
+#[endpoint(
+    operation_id = "admin_revoke_invite",
+    tags("Admin"),
+    responses(
+        (status_code = 204, description = "Invite token revoked"),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not an admin", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The invite token does not exist", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn revoke_invite(depot: &mut Depot, token: PathParam<String>) -> ApiResult<EmptySchema> {
+    depot.db_conn().revoke_invite_token(&token).await?;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// Returns the user that the admin will ban or delete, the admins can't be
+/// banned or deleted
+async fn moderated_user(conn: &DatabaseConnection, public_key: &PublicKey) -> ApiResult<UserModel> {
+    let user = conn
+        .get_user_by_pubk(public_key)
+        .await?
+        .ok_or(ApiError::UserNotFound)?;
+    if user.is_admin {
+        return Err(ApiError::CannotModerateAdmin);
+    }
+    Ok(user)
+}
+
+/// The route of the endpoints of this module
+pub fn route() -> Router {
+    Router::new()
+        .push(
+            Router::with_path("users").get(users).push(
+                Router::with_path("<public_key>")
+                    .delete(delete_user)
+                    .push(Router::with_path("ban").put(ban_user).delete(unban_user)),
+            ),
+        )
+        .push(
+            Router::with_path("registration")
+                .get(registration)
+                .put(set_registration),
+        )
+        .push(
+            Router::with_path("invites")
+                .get(invites)
+                .post(mint_invite)
+                .push(Router::with_path("<token>").delete(revoke_invite)),
+        )
+        .push(Router::with_path("online").get(online))
+        .hoop(middlewares::signature_check)
+        .hoop(middlewares::admin_check)
+}
@@ -0,0 +1,65 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Discovery endpoint, describes the homeserver to the clients and the remote
+//! homeservers
+
+use salvo::{oapi::endpoint, writing::Json, Depot, Router};
+
+use crate::{
+    extensions::DepotExt,
+    schemas::{DiscoveryDocument, MessageSchema, SignedDiscoveryDocument},
+    websocket::PROTOCOL_VERSION,
+};
+
+/// (🔓) Homeserver discovery document
+///
+/// Returns the homeserver name, public key, protocol version, registration
+/// status and websocket URL. The document is signed with the homeserver ECDSA
+/// key, so anyone can verify it with the public key in the document and pin
+/// the homeserver identity.
+#[endpoint(
+    operation_id = "discovery",
+    tags("Discovery"),
+    responses(
+        (status_code = 200, description = "Returns the signed discovery document", content_type = "application/json", body = SignedDiscoveryDocument),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+    ),
+)]
+async fn discovery(depot: &mut Depot) -> Json<SignedDiscoveryDocument> {
+    let config = depot.config();
+    let document = DiscoveryDocument::new(
+        config.server.server_name.clone(),
+        config.server.private_key.pubkey(),
+        PROTOCOL_VERSION,
+        depot.registration().is_open(),
+        format!(
+            "{}/ws/chat",
+            depot
+                .federation()
+                .server_url(&config.server.server_name)
+                .replacen("http", "ws", 1)
+        ),
+    );
+    let signature = config.server.private_key.sign_ecdsa(&document.data());
+
+    Json(SignedDiscoveryDocument::new(document, signature))
+}
+
+/// The route of the endpoints of this module
+pub fn route() -> Router {
+    Router::with_path("otmp").get(discovery)
+}
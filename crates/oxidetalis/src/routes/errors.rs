@@ -44,6 +44,52 @@ pub enum ApiError {
     /// (403 Forbidden)
     #[error("You are not a registered user, please register first")]
     NotRegisteredUser,
+    /// The user is already on the whitelist (409 Conflict)
+    #[error("The user is already on your whitelist")]
+    AlreadyOnTheWhitelist,
+    /// The user is already on the blacklist (409 Conflict)
+    #[error("The user is already on your blacklist")]
+    AlreadyOnTheBlacklist,
+    /// The user tried to add themself to the whitelist (400 Bad Request)
+    #[error("You cannot add yourself to the whitelist")]
+    CannotAddSelfToWhitelist,
+    /// The user tried to add themself to the blacklist (400 Bad Request)
+    #[error("You cannot add yourself to the blacklist")]
+    CannotAddSelfToBlacklist,
+    /// The user is not on the whitelist (404 Not Found)
+    #[error("The user is not on your whitelist")]
+    NotOnTheWhitelist,
+    /// The user is not on the blacklist (404 Not Found)
+    #[error("The user is not on your blacklist")]
+    NotOnTheBlacklist,
+    /// The user is not registered in the server (404 Not Found)
+    #[error("The user is not registered in the server")]
+    UserNotFound,
+    /// The chat request sender is on the recipient's blacklist (403 Forbidden)
+    #[error("The sender is on the recipient's blacklist")]
+    RecipientBlacklist,
+    /// The chat request sender is already on the recipient's whitelist (409
+    /// Conflict)
+    #[error("The sender is already on the recipient's whitelist")]
+    AlreadyInRecipientWhitelist,
+    /// There is no chat request to the chat response sender (404 Not Found)
+    #[error("There is no chat request to the response sender")]
+    NoChatRequestToResponder,
+    /// The request sender is not a homeserver admin (403 Forbidden)
+    #[error("You are not an admin of the homeserver")]
+    NotAdmin,
+    /// The request sender is banned from the homeserver (403 Forbidden)
+    #[error("You are banned from the homeserver")]
+    BannedUser,
+    /// The admin tried to ban or delete an admin (403 Forbidden)
+    #[error("You cannot ban or delete an admin")]
+    CannotModerateAdmin,
+    /// The invite token is invalid, expired or used up (403 Forbidden)
+    #[error("The invite token is invalid, expired or used up")]
+    InvalidInviteToken,
+    /// The invite token doesn't exist (404 Not Found)
+    #[error("The invite token does not exist")]
+    InviteTokenNotFound,
 }
 
 impl ApiError {
@@ -51,8 +97,25 @@ impl ApiError {
     pub const fn status_code(&self) -> StatusCode {
         match self {
             Self::Internal => StatusCode::INTERNAL_SERVER_ERROR,
-            Self::RegistrationClosed | Self::NotRegisteredUser => StatusCode::FORBIDDEN,
-            Self::AlreadyRegistered | Self::Querys(_) => StatusCode::BAD_REQUEST,
+            Self::RegistrationClosed
+            | Self::NotRegisteredUser
+            | Self::RecipientBlacklist
+            | Self::NotAdmin
+            | Self::BannedUser
+            | Self::CannotModerateAdmin
+            | Self::InvalidInviteToken => StatusCode::FORBIDDEN,
+            Self::AlreadyRegistered
+            | Self::Querys(_)
+            | Self::CannotAddSelfToWhitelist
+            | Self::CannotAddSelfToBlacklist => StatusCode::BAD_REQUEST,
+            Self::AlreadyOnTheWhitelist
+            | Self::AlreadyOnTheBlacklist
+            | Self::AlreadyInRecipientWhitelist => StatusCode::CONFLICT,
+            Self::NotOnTheWhitelist
+            | Self::NotOnTheBlacklist
+            | Self::UserNotFound
+            | Self::NoChatRequestToResponder
+            | Self::InviteTokenNotFound => StatusCode::NOT_FOUND,
         }
     }
 }
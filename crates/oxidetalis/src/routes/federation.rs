@@ -0,0 +1,155 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Federation endpoints, the endpoints that the remote homeservers use to
+//! deliver the events of their users
+
+use oxidetalis_core::types::Signature;
+use salvo::{
+    http::StatusCode,
+    oapi::{endpoint, extract::JsonBody},
+    Depot,
+    Request,
+    Router,
+    Writer,
+};
+
+use super::{ApiError, ApiResult};
+use crate::{
+    database::{IncomingChatExt, OutChatRequestsExt, UserTableExt, UsersStatusExt},
+    extensions::{DepotExt, OnlineUsersExt},
+    federation::{self, UserAddress},
+    middlewares,
+    schemas::{EmptySchema, FederationEvent, MessageSchema},
+    websocket::{ServerEvent, UnackedEvent, ONLINE_USERS},
+};
+
+/// (🔐) Receive an event from a remote homeserver
+///
+/// The request must be signed by the remote homeserver key, and the
+/// `X-OTMP-SERVER` header must be the remote homeserver name.
+#[endpoint(
+    operation_id = "federation_event",
+    tags("Federation"),
+    responses(
+        (status_code = 204, description = "The event is delivered"),
+        (status_code = 400, description = "Invalid event or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature or homeserver identity", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "The sender is on the recipient's blacklist", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The recipient is not found, or there is no chat request to the response sender", content_type = "application/json", body = MessageSchema),
+        (status_code = 409, description = "The sender is already on the recipient's whitelist", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn receive_event(
+    req: &mut Request,
+    depot: &mut Depot,
+    event: JsonBody<FederationEvent>,
+) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    // The federation middleware checked that the header is there
+    let server = federation::sender_server(req).ok_or(ApiError::Internal)?;
+
+    match event.into_inner() {
+        FederationEvent::ChatRequest {
+            from,
+            to,
+            signature,
+        } => {
+            let recipient = conn
+                .get_user_by_pubk(&to)
+                .await?
+                .ok_or(ApiError::UserNotFound)?;
+            if conn.is_blacklisted(&recipient, &from).await? {
+                return Err(ApiError::RecipientBlacklist);
+            }
+            if conn.is_whitelisted(&recipient, &from).await? {
+                return Err(ApiError::AlreadyInRecipientWhitelist);
+            }
+
+            let sender = UserAddress::new(from, Some(server));
+            if let Some(incoming_chat) = conn
+                .save_in_chat_request(&recipient, &sender, signature)
+                .await?
+            {
+                ONLINE_USERS
+                    .send_stored_to_user(
+                        &recipient.public_key,
+                        &ServerEvent::chat_request(sender, signature),
+                        UnackedEvent::IncomingChat(incoming_chat),
+                    )
+                    .await;
+            }
+        }
+        FederationEvent::ChatRequestResponse { accepted, from, to } => {
+            let requester = conn
+                .get_user_by_pubk(&to)
+                .await?
+                .ok_or(ApiError::UserNotFound)?;
+            // Only the homeserver of the recipient can respond to the chat
+            // request, the requests to the local users are answered locally
+            if conn
+                .get_chat_request_to(&requester, &from)
+                .await?
+                .filter(|request| request.recipient_server.as_ref() == Some(&server))
+                .is_none()
+            {
+                return Err(ApiError::NoChatRequestToResponder);
+            }
+            conn.remove_out_chat_request(&requester, &from).await?;
+
+            let responder = UserAddress::new(from, Some(server));
+            if let Some(incoming_chat) = conn
+                .save_in_chat_response(&requester, &responder, accepted)
+                .await?
+            {
+                ONLINE_USERS
+                    .send_stored_to_user(
+                        &requester.public_key,
+                        &ServerEvent::chat_request_response(responder, accepted),
+                        UnackedEvent::IncomingChat(incoming_chat),
+                    )
+                    .await;
+            }
+        }
+        FederationEvent::CancelChatRequest { from, to } => {
+            let recipient = conn
+                .get_user_by_pubk(&to)
+                .await?
+                .ok_or(ApiError::UserNotFound)?;
+            let sender = UserAddress::new(from, Some(server));
+            conn.remove_in_chat_request(&recipient, &sender).await?;
+            ONLINE_USERS
+                .send_to_user(
+                    &recipient.public_key,
+                    &ServerEvent::chat_request_cancelled(sender),
+                )
+                .await;
+        }
+    }
+
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// The route of the endpoints of this module
+pub fn route() -> Router {
+    Router::with_path("events")
+        .post(receive_event)
+        .hoop(middlewares::signature_check)
+        .hoop(middlewares::federation_check)
+}
@@ -25,11 +25,17 @@ use salvo::oapi::{Info, License};
 use salvo::rate_limiter::{BasicQuota, FixedGuard, MokaStore, RateLimiter, RemoteIpIssuer};
 use salvo::{catcher::Catcher, logging::Logger, prelude::*};
 
+use crate::federation::Federation;
 use crate::nonce::NonceCache;
+use crate::registration::Registration;
 use crate::schemas::MessageSchema;
+use crate::secret_cache::SecretCache;
 use crate::{middlewares, websocket};
 
+mod admin;
+mod discovery;
 mod errors;
+mod federation;
 mod user;
 
 pub use errors::*;
@@ -133,19 +139,39 @@ pub fn service(conn: sea_orm::DatabaseConnection, config: &Config) -> Service {
         config.server.nonce_cache_size
     );
 
+    let secret_cache = SecretCache::new(&config.server.secret_cache_size);
+    log::info!(
+        "Secret cache created with a capacity of {}",
+        config.server.secret_cache_size
+    );
+
+    let conn = Arc::new(conn);
+    let config = Arc::new(config.clone());
+    let federation = Arc::new(Federation::new(&config));
+
     let router = Router::new()
         .push(Router::with_path("user").push(user::route()))
-        .push(Router::with_path("ws").push(websocket::route()))
+        .push(Router::with_path("admin").push(admin::route()))
+        .push(Router::with_path("federation").push(federation::route()))
+        .push(Router::with_path(".well-known").push(discovery::route()))
+        .push(Router::with_path("ws").push(websocket::route(
+            Arc::clone(&conn),
+            Arc::clone(&config),
+            Arc::clone(&federation),
+        )))
         .hoop(middlewares::add_server_headers)
         .hoop(Logger::new())
         .hoop(
-            affix::inject(Arc::new(conn))
-                .inject(Arc::new(config.clone()))
-                .inject(Arc::new(nonce_cache)),
+            affix::inject(conn)
+                .inject(Arc::clone(&config))
+                .inject(Arc::new(nonce_cache))
+                .inject(Arc::new(secret_cache))
+                .inject(federation)
+                .inject(Arc::new(Registration::new(&config.register))),
         );
 
-    let router = hoop_if(router, ratelimiter(config), config.ratelimit.enable);
-    let router = route_openapi(config, router);
+    let router = hoop_if(router, ratelimiter(&config), config.ratelimit.enable);
+    let router = route_openapi(&config, router);
 
     Service::new(router).catcher(
         Catcher::default()
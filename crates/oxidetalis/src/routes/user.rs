@@ -17,21 +17,38 @@
 //! REST API endpoints for user management
 
 use oxidetalis_core::types::{PublicKey, Signature};
-use salvo::{http::StatusCode, oapi::endpoint, writing::Json, Depot, Router, Writer};
+use salvo::{
+    http::StatusCode,
+    oapi::{
+        endpoint,
+        extract::{PathParam, QueryParam},
+    },
+    writing::Json,
+    Depot,
+    Router,
+    Writer,
+};
 
 use super::{ApiError, ApiResult};
 use crate::{
-    database::{UserTableExt, UsersStatusExt},
+    database::{IncomingChatExt, OutChatRequestsExt, UserTableExt, UsersStatusExt},
     extensions::DepotExt,
     middlewares,
     parameters::Pagination,
-    schemas::{BlackListedUser, EmptySchema, MessageSchema, WhiteListedUser},
+    schemas::{
+        BlackListedUser,
+        EmptySchema,
+        IncomingChatRequest,
+        MessageSchema,
+        OutgoingChatRequest,
+        WhiteListedUser,
+    },
 };
 
 /// (🔓) Register a user
 ///
 /// Register the request sender as a user in the server, the server registration
-/// must be open to register a user.
+/// must be open to register a user, unless a valid invite token is given.
 #[endpoint(
     operation_id = "register",
     tags("User"),
@@ -39,21 +56,26 @@ use crate::{
         (status_code = 201, description = "User registered"),
         (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
         (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
-        (status_code = 403, description = "Server registration is closed", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Server registration is closed, or the invite token is invalid, expired or used up", content_type = "application/json", body = MessageSchema),
         (status_code = 409, description = "The entered public key is already registered", content_type = "application/json", body = MessageSchema),
         (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
         (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
     ),
     parameters(Signature),
 )]
-pub async fn register(public_key: PublicKey, depot: &mut Depot) -> ApiResult<EmptySchema> {
+pub async fn register(
+    public_key: PublicKey,
+    invite_token: QueryParam<String, false>,
+    depot: &mut Depot,
+) -> ApiResult<EmptySchema> {
     let db = depot.db_conn();
-    let config = depot.config();
 
     if !db.users_exists_in_database().await? {
         db.register_user(&public_key, true).await?;
-    } else if config.register.enable {
+    } else if depot.registration().is_open() {
         db.register_user(&public_key, false).await?;
+    } else if let Some(invite_token) = invite_token.into_inner() {
+        db.register_invited_user(&public_key, &invite_token).await?;
     } else {
         return Err(ApiError::RegistrationClosed);
     }
@@ -127,11 +149,232 @@ async fn user_blacklist(
     ))
 }
 
+/// (🔐) Add a user to the whitelist
+///
+/// Add the user to the whitelist of the request sender, if the user is on the
+/// blacklist, it will be removed from it.
+#[endpoint(
+    operation_id = "add_to_whitelist",
+    tags("User"),
+    responses(
+        (status_code = 204, description = "User added to the whitelist"),
+        (status_code = 400, description = "Invalid public key or adding yourself", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not registered user, must register first", content_type = "application/json", body = MessageSchema),
+        (status_code = 409, description = "The user is already on the whitelist", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn add_to_whitelist(
+    depot: &mut Depot,
+    public_key: PathParam<PublicKey>,
+    sender_public_key: PublicKey,
+) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = conn
+        .get_user_by_pubk(&sender_public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    conn.add_to_whitelist(&user, &public_key).await?;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Remove a user from the whitelist
+///
+/// Remove the user from the whitelist of the request sender.
+#[endpoint(
+    operation_id = "remove_from_whitelist",
+    tags("User"),
+    responses(
+        (status_code = 204, description = "User removed from the whitelist"),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not registered user, must register first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The user is not on the whitelist", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn remove_from_whitelist(
+    depot: &mut Depot,
+    public_key: PathParam<PublicKey>,
+    sender_public_key: PublicKey,
+) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = conn
+        .get_user_by_pubk(&sender_public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    if !conn.is_whitelisted(&user, &public_key).await? {
+        return Err(ApiError::NotOnTheWhitelist);
+    }
+    conn.remove_from_whitelist(&user, &public_key).await?;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Add a user to the blacklist
+///
+/// Add the user to the blacklist of the request sender, if the user is on the
+/// whitelist, it will be removed from it.
+#[endpoint(
+    operation_id = "add_to_blacklist",
+    tags("User"),
+    responses(
+        (status_code = 204, description = "User added to the blacklist"),
+        (status_code = 400, description = "Invalid public key or adding yourself", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not registered user, must register first", content_type = "application/json", body = MessageSchema),
+        (status_code = 409, description = "The user is already on the blacklist", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn add_to_blacklist(
+    depot: &mut Depot,
+    public_key: PathParam<PublicKey>,
+    sender_public_key: PublicKey,
+) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = conn
+        .get_user_by_pubk(&sender_public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    conn.add_to_blacklist(&user, &public_key).await?;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Remove a user from the blacklist
+///
+/// Remove the user from the blacklist of the request sender.
+#[endpoint(
+    operation_id = "remove_from_blacklist",
+    tags("User"),
+    responses(
+        (status_code = 204, description = "User removed from the blacklist"),
+        (status_code = 400, description = "Invalid public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not registered user, must register first", content_type = "application/json", body = MessageSchema),
+        (status_code = 404, description = "The user is not on the blacklist", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn remove_from_blacklist(
+    depot: &mut Depot,
+    public_key: PathParam<PublicKey>,
+    sender_public_key: PublicKey,
+) -> ApiResult<EmptySchema> {
+    let conn = depot.db_conn();
+    let user = conn
+        .get_user_by_pubk(&sender_public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    if !conn.is_blacklisted(&user, &public_key).await? {
+        return Err(ApiError::NotOnTheBlacklist);
+    }
+    conn.remove_from_blacklist(&user, &public_key).await?;
+    Ok(EmptySchema::new(StatusCode::NO_CONTENT))
+}
+
+/// (🔐) Get incoming chat requests
+///
+/// Returns the chat requests that were sent to the user, from this homeserver
+/// and the remote homeservers, until the user acknowledges them.
+#[endpoint(
+    operation_id = "incoming_chat_requests",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "Returns incoming chat requests", content_type = "application/json", body = Vec<IncomingChatRequest>),
+        (status_code = 400, description = "Invalid parameters or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not registered user, must register first", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn user_incoming_chat_requests(
+    depot: &mut Depot,
+    pagination: Pagination,
+    public_key: PublicKey,
+) -> ApiResult<Json<Vec<IncomingChatRequest>>> {
+    let conn = depot.db_conn();
+    let user = conn
+        .get_user_by_pubk(&public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    Ok(Json(
+        conn.user_incoming_chat_requests(&user, pagination.page, pagination.page_size)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    ))
+}
+
+/// (🔐) Get outgoing chat requests
+///
+/// Returns the chat requests that the user sent, and are not answered yet.
+#[endpoint(
+    operation_id = "outgoing_chat_requests",
+    tags("User"),
+    responses(
+        (status_code = 200, description = "Returns outgoing chat requests", content_type = "application/json", body = Vec<OutgoingChatRequest>),
+        (status_code = 400, description = "Invalid parameters or public key", content_type = "application/json", body = MessageSchema),
+        (status_code = 401, description = "Invalid signature", content_type = "application/json", body = MessageSchema),
+        (status_code = 403, description = "Not registered user, must register first", content_type = "application/json", body = MessageSchema),
+        (status_code = 429, description = "Too many requests", content_type = "application/json", body = MessageSchema),
+        (status_code = 500, description = "Internal server error", content_type = "application/json", body = MessageSchema),
+    ),
+    parameters(Signature),
+)]
+async fn user_outgoing_chat_requests(
+    depot: &mut Depot,
+    pagination: Pagination,
+    public_key: PublicKey,
+) -> ApiResult<Json<Vec<OutgoingChatRequest>>> {
+    let conn = depot.db_conn();
+    let user = conn
+        .get_user_by_pubk(&public_key)
+        .await?
+        .ok_or(ApiError::NotRegisteredUser)?;
+    Ok(Json(
+        conn.user_outgoing_chat_requests(&user, pagination.page, pagination.page_size)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect(),
+    ))
+}
+
 /// The route of the endpoints of this module
 pub fn route() -> Router {
     Router::new()
         .push(Router::with_path("register").post(register))
-        .push(Router::with_path("whitelist").get(user_whitelist))
-        .push(Router::with_path("blacklist").get(user_blacklist))
+        .push(
+            Router::with_path("whitelist").get(user_whitelist).push(
+                Router::with_path("<public_key>")
+                    .put(add_to_whitelist)
+                    .delete(remove_from_whitelist),
+            ),
+        )
+        .push(
+            Router::with_path("blacklist").get(user_blacklist).push(
+                Router::with_path("<public_key>")
+                    .put(add_to_blacklist)
+                    .delete(remove_from_blacklist),
+            ),
+        )
+        .push(
+            Router::with_path("chat-requests")
+                .push(Router::with_path("incoming").get(user_incoming_chat_requests))
+                .push(Router::with_path("outgoing").get(user_outgoing_chat_requests)),
+        )
         .hoop(middlewares::signature_check)
+        .hoop(middlewares::ban_check)
 }
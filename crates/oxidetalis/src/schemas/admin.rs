@@ -0,0 +1,149 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Admin API schemas
+
+use std::{num::NonZeroU32, str::FromStr};
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use oxidetalis_core::types::PublicKey;
+use oxidetalis_entities::prelude::*;
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+
+/// User info schema, represents a registered user of the homeserver.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = UserInfo, example = json!(UserInfo::default())))]
+pub struct UserInfo {
+    /// User's public key
+    pub public_key:  PublicKey,
+    /// Whether the user is an admin
+    pub is_admin:    bool,
+    /// Whether the user is banned
+    pub is_banned:   bool,
+    /// When the user last disconnected
+    pub last_logout: DateTime<Utc>,
+}
+
+/// Registration status schema
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = RegistrationStatus, example = json!(RegistrationStatus::new(true))))]
+pub struct RegistrationStatus {
+    /// Whether the registration is open
+    pub open: bool,
+}
+
+/// Online users schema, the number of the online users and their connections
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = OnlineUsersCount, example = json!(OnlineUsersCount::new(3, 2))))]
+pub struct OnlineUsersCount {
+    /// Number of the online connections
+    pub connections: usize,
+    /// Number of the online users, a user may have multiple connections
+    pub users:       usize,
+}
+
+/// New invite token schema, used to mint an invite token
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = NewInviteToken, example = json!(NewInviteToken::default())))]
+pub struct NewInviteToken {
+    /// How many times the token can be used to register
+    #[salvo(schema(value_type = u32, minimum = 1))]
+    pub max_uses:        NonZeroU32,
+    /// After how many seconds the token will expire
+    #[salvo(schema(value_type = u32, minimum = 1))]
+    pub expires_in_secs: NonZeroU32,
+}
+
+/// Invite token schema, represents a minted invite token
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = InviteToken, example = json!(InviteToken::default())))]
+pub struct InviteToken {
+    /// The token, used as `invite_token` query of the registration
+    pub token:      String,
+    /// How many times the token can be used to register
+    pub max_uses:   i32,
+    /// How many times the token has been used
+    pub uses:       i32,
+    /// When the token was minted
+    pub created_at: DateTime<Utc>,
+    /// When the token will expire
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Default for UserInfo {
+    fn default() -> Self {
+        UserInfo::new(
+            PublicKey::from_str("bYhbrm61ov8GLZfskUYbsCLJTfaacMsuTBYgBABEH9dy").expect("is valid"),
+            false,
+            false,
+            NaiveDateTime::new(
+                NaiveDate::from_ymd_opt(2015, 5, 16).expect("Is valid date"),
+                NaiveTime::from_hms_opt(12, 17, 20).expect("Is valid time"),
+            )
+            .and_utc(),
+        )
+    }
+}
+
+impl From<UserModel> for UserInfo {
+    fn from(user: UserModel) -> Self {
+        Self {
+            public_key:  user.public_key,
+            is_admin:    user.is_admin,
+            is_banned:   user.is_banned,
+            last_logout: user.last_logout,
+        }
+    }
+}
+
+impl Default for NewInviteToken {
+    fn default() -> Self {
+        NewInviteToken::new(
+            NonZeroU32::new(1).expect("is non-zero"),
+            NonZeroU32::new(86400).expect("is non-zero"),
+        )
+    }
+}
+
+impl Default for InviteToken {
+    fn default() -> Self {
+        let created_at = NaiveDateTime::new(
+            NaiveDate::from_ymd_opt(2015, 5, 16).expect("Is valid date"),
+            NaiveTime::from_hms_opt(12, 17, 20).expect("Is valid time"),
+        )
+        .and_utc();
+        InviteToken::new(
+            "3Q7s2dbT8sWcYJoGpu6MVbLfWjAtBgTAqX3cqwsbNXEh".to_owned(),
+            1,
+            0,
+            created_at,
+            created_at + TimeDelta::days(1),
+        )
+    }
+}
+
+impl From<InviteTokensModel> for InviteToken {
+    fn from(token: InviteTokensModel) -> Self {
+        Self {
+            token:      token.token,
+            max_uses:   token.max_uses,
+            uses:       token.uses,
+            created_at: token.created_at,
+            expires_at: token.expires_at,
+        }
+    }
+}
@@ -0,0 +1,86 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Federation API schemas
+
+use oxidetalis_core::types::{PublicKey, Signature};
+use salvo::oapi::ToSchema;
+use serde::{Deserialize, Serialize};
+
+/// Discovery document schema, describes the homeserver identity and how to
+/// connect to it.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = DiscoveryDocument))]
+pub struct DiscoveryDocument {
+    /// The homeserver name
+    pub server_name:       String,
+    /// The homeserver public key (compressed)
+    pub public_key:        PublicKey,
+    /// The OTMP protocol version that the homeserver speaks
+    pub protocol_version:  u16,
+    /// Whether the homeserver registration is open
+    pub registration_open: bool,
+    /// The websocket URL of the homeserver
+    pub websocket_url:     String,
+}
+
+/// Signed discovery document schema, the signature is the ECDSA signature of
+/// the document JSON, signed with the homeserver private key.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = SignedDiscoveryDocument))]
+pub struct SignedDiscoveryDocument {
+    /// The discovery document
+    pub document:  DiscoveryDocument,
+    /// The document signature
+    pub signature: Signature,
+}
+
+impl DiscoveryDocument {
+    /// Returns the document data that is signed
+    pub fn data(&self) -> Vec<u8> {
+        serde_json::to_value(self)
+            .expect("Can't fail")
+            .to_string()
+            .into_bytes()
+    }
+}
+
+/// Federation event schema, an event sent from a homeserver to another on
+/// behalf of its users.
+///
+/// The `from` user is a user of the sender homeserver, and the `to` user is a
+/// user of the recipient homeserver.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema)]
+#[serde(rename_all = "PascalCase", tag = "event", content = "data")]
+#[salvo(schema(name = FederationEvent))]
+pub enum FederationEvent {
+    /// Chat request to a user, with the sender ECDSA signature of their
+    /// `ChatRequest` event data if they signed it with ECDSA
+    ChatRequest {
+        from:      PublicKey,
+        to:        PublicKey,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        signature: Option<Signature>,
+    },
+    /// Response to a chat request
+    ChatRequestResponse {
+        accepted: bool,
+        from:     PublicKey,
+        to:       PublicKey,
+    },
+    /// Cancel a sent chat request
+    CancelChatRequest { from: PublicKey, to: PublicKey },
+}
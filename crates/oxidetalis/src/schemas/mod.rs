@@ -29,8 +29,12 @@ use salvo::{
 };
 use serde::{Deserialize, Serialize};
 
+mod admin;
+mod federation;
 mod user;
 
+pub use admin::*;
+pub use federation::*;
 pub use user::*;
 
 /// Message schema, used for returning messages.
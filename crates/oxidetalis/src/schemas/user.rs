@@ -19,7 +19,7 @@
 use std::str::FromStr;
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
-use oxidetalis_core::types::PublicKey;
+use oxidetalis_core::types::{PublicKey, Signature};
 use oxidetalis_entities::prelude::*;
 use salvo::oapi::ToSchema;
 use serde::{Deserialize, Serialize};
@@ -44,6 +44,35 @@ pub struct BlackListedUser {
     pub blacklisted_at: DateTime<Utc>,
 }
 
+/// Incoming chat request schema, represents a chat request sent to the user
+/// and not answered yet.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = IncomingChatRequest, example = json!(IncomingChatRequest::default())))]
+pub struct IncomingChatRequest {
+    /// The requester public key
+    pub public_key:   PublicKey,
+    /// The requester homeserver, `null` if they are a user of this homeserver
+    pub server:       Option<String>,
+    /// When the request was received
+    pub requested_at: DateTime<Utc>,
+    /// The requester ECDSA signature of their `ChatRequest` event data, `null`
+    /// if they didn't sign it with ECDSA
+    pub signature:    Option<Signature>,
+}
+
+/// Outgoing chat request schema, represents a chat request sent by the user
+/// and not answered yet.
+#[derive(Serialize, Deserialize, Clone, Debug, ToSchema, derive_new::new)]
+#[salvo(schema(name = OutgoingChatRequest, example = json!(OutgoingChatRequest::default())))]
+pub struct OutgoingChatRequest {
+    /// The recipient public key
+    pub public_key:   PublicKey,
+    /// The recipient homeserver, `null` if they are a user of this homeserver
+    pub server:       Option<String>,
+    /// When the request was sent
+    pub requested_at: DateTime<Utc>,
+}
+
 impl Default for WhiteListedUser {
     fn default() -> Self {
         WhiteListedUser::new(
@@ -87,3 +116,53 @@ impl From<UsersStatusModel> for BlackListedUser {
         }
     }
 }
+
+impl Default for IncomingChatRequest {
+    fn default() -> Self {
+        IncomingChatRequest::new(
+            PublicKey::from_str("bYhbrm61ov8GLZfskUYbsCLJTfaacMsuTBYgBABEH9dy").expect("is valid"),
+            Some("example.com".to_owned()),
+            NaiveDateTime::new(
+                NaiveDate::from_ymd_opt(2015, 5, 16).expect("Is valid date"),
+                NaiveTime::from_hms_opt(12, 17, 20).expect("Is valid time"),
+            )
+            .and_utc(),
+            None,
+        )
+    }
+}
+
+impl From<IncomingChatModel> for IncomingChatRequest {
+    fn from(request: IncomingChatModel) -> Self {
+        Self {
+            public_key:   request.sender,
+            server:       request.sender_server,
+            requested_at: request.received_timestamp,
+            signature:    request.signature,
+        }
+    }
+}
+
+impl Default for OutgoingChatRequest {
+    fn default() -> Self {
+        OutgoingChatRequest::new(
+            PublicKey::from_str("bYhbrm61ov8GLZfskUYbsCLJTfaacMsuTBYgBABEH9dy").expect("is valid"),
+            None,
+            NaiveDateTime::new(
+                NaiveDate::from_ymd_opt(2015, 5, 16).expect("Is valid date"),
+                NaiveTime::from_hms_opt(12, 17, 20).expect("Is valid time"),
+            )
+            .and_utc(),
+        )
+    }
+}
+
+impl From<OutChatRequestsModel> for OutgoingChatRequest {
+    fn from(request: OutChatRequestsModel) -> Self {
+        Self {
+            public_key:   request.recipient,
+            server:       request.recipient_server,
+            requested_at: request.out_on,
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Cache of the keys derived from the ECDH shared secrets
+
+use std::{collections::HashMap, mem};
+
+use oxidetalis_core::{
+    cipher::{K256Secret, KeyPurpose},
+    types::{PublicKey, Size},
+};
+use tokio::sync::Mutex as TokioMutex;
+use zeroize::{Zeroize, Zeroizing};
+
+use crate::nonce::HASH_MAP_SIZE;
+
+/// Size of each entry in the secret cache
+pub(crate) const SECRET_ENTRY_SIZE: usize =
+    mem::size_of::<PublicKey>() + mem::size_of::<Zeroizing<[u8; 32]>>() + mem::size_of::<u64>();
+
+/// A cached key, with the tick of its last use
+struct CachedKey {
+    /// The derived key, zeroized when it's dropped
+    key:       Zeroizing<[u8; 32]>,
+    /// The cache tick of the last use of the key
+    last_used: u64,
+}
+
+/// Secret cache struct, used to cache the client-server authentication keys
+/// of the users and the homeservers, to avoid the ECDH and HKDF computation on
+/// each request.
+///
+/// The cache will remove the least recently used 10% keys if the cache limit
+/// is reached, the removed keys are zeroized.
+pub struct SecretCache {
+    /// The cache hashmap and the cache tick
+    cache: TokioMutex<(HashMap<PublicKey, CachedKey>, u64)>,
+}
+
+impl SecretCache {
+    /// Creates new [`SecretCache`] instance, with the given cache limit
+    pub fn new(cache_limit: &Size) -> Self {
+        Self {
+            cache: TokioMutex::new((
+                HashMap::with_capacity(
+                    cache_limit.as_bytes().saturating_sub(HASH_MAP_SIZE) / SECRET_ENTRY_SIZE,
+                ),
+                0,
+            )),
+        }
+    }
+
+    /// Returns the client-server authentication key with the public key, from
+    /// the cache or derived by the private key and cached.
+    pub async fn auth_key(
+        &self,
+        private_key: &K256Secret,
+        public_key: &PublicKey,
+    ) -> Zeroizing<[u8; 32]> {
+        {
+            let (cache, tick) = &mut *self.cache.lock().await;
+            *tick += 1;
+            if let Some(cached) = cache.get_mut(public_key) {
+                cached.last_used = *tick;
+                return cached.key.clone();
+            }
+        }
+
+        // Derive the key without holding the lock, it's the expensive part
+        let key = Zeroizing::new(private_key.derive_key(public_key, KeyPurpose::ClientServerAuth));
+
+        let (cache, tick) = &mut *self.cache.lock().await;
+        if cache.capacity() == 0 {
+            return key;
+        }
+        if cache.len() == cache.capacity() && !cache.contains_key(public_key) {
+            log::warn!("Secret cache limit reached, clearing 10% of the cache");
+            let mut keys: Vec<(u64, PublicKey)> = cache
+                .iter()
+                .map(|(public_key, cached)| (cached.last_used, *public_key))
+                .collect();
+            keys.sort_unstable_by_key(|(last_used, _)| *last_used);
+            for (_, public_key) in keys.iter().take((cache.capacity() / 10).max(1)) {
+                // Zeroize the key in its slot, removing it moves it out of the
+                // slot and only zeroizes the moved copy
+                if let Some(cached) = cache.get_mut(public_key) {
+                    cached.key.zeroize();
+                }
+                cache.remove(public_key);
+            }
+        }
+        cache.insert(
+            *public_key,
+            CachedKey {
+                key:       key.clone(),
+                last_used: *tick,
+            },
+        );
+        key
+    }
+}
@@ -18,9 +18,6 @@
 
 use crate::ws_errors;
 
-/// Result type of websocket
-pub type WsResult<T> = Result<T, WsError>;
-
 ws_errors! {
     InternalServerError = "Internal server error",
     InvalidSignature = "Invalid event signature",
@@ -33,10 +30,19 @@ ws_errors! {
     CannotAddSelfToWhitelist = "You cannot add yourself to the whitelist",
     AlreadyOnTheBlacklist = "The user is already on your blacklist",
     CannotAddSelfToBlacklist = "You cannot add yourself to the blacklist",
+    NotOnTheWhitelist = "The user is not on your whitelist",
+    NotOnTheBlacklist = "The user is not on your blacklist",
     AlreadySendChatRequest = "You have already sent a chat request to this user",
     CannotSendChatRequestToSelf = "You cannot send a chat request to yourself",
     CannotRespondToOwnChatRequest = "You cannot respond to your own chat request",
     NoChatRequestFromRecipient = "You do not have a chat request from the recipient",
+    NoChatRequestToRecipient = "You have not sent a chat request to the recipient",
     RecipientBlacklist = "You cannot send a chat request because you are on the recipient's blacklist.",
-    AlreadyInRecipientWhitelist = "You are already on the recipient's whitelist and can chat with them."
+    AlreadyInRecipientWhitelist = "You are already on the recipient's whitelist and can chat with them.",
+    CannotSendMessageToSelf = "You cannot send a message to yourself",
+    NotMutuallyWhitelisted = "You cannot send a message unless you and the recipient are on each other's whitelist",
+    RecipientQueueFull = "The recipient's offline queue is full, try again later",
+    IncompatibleClient = "The client protocol version is not supported by the server",
+    FederationFailed = "Couldn't deliver the event to the recipient's homeserver",
+    ServerNotAllowed = "The recipient's homeserver is not allowed to federate with this homeserver"
 }
@@ -16,37 +16,76 @@
 
 //! Events that the client send it
 
-use oxidetalis_core::types::{PublicKey, Signature};
+use oxidetalis_core::types::{PublicKey, Signature, SignatureVersion};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::{nonce::NonceCache, utils};
+use crate::{federation::UserAddress, named_variants, nonce::NonceCache, utils};
 
 /// Client websocket event
 #[derive(Deserialize, Clone, Debug)]
 pub struct ClientEvent {
+    /// Optional client chosen id, echoed in the server response of the event
+    pub id:    Option<String>,
     #[serde(flatten)]
     pub event: ClientEventType,
     signature: Signature,
 }
 
+/// The id of a client event, used to echo the id of the invalid events
+#[derive(Deserialize)]
+pub struct ClientEventId {
+    pub id: Option<String>,
+}
+
 // ## Important for contuributors
 // Please make sure to order the event data alphabetically.
 
-/// Client websocket event type
-#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
-#[serde(rename_all = "PascalCase", tag = "event", content = "data")]
-pub enum ClientEventType {
-    /// Ping event
-    Ping { timestamp: u64 },
-    /// Pong event
-    Pong { timestamp: u64 },
-    /// Request to chat with a user
-    ChatRequest { to: PublicKey },
-    /// Response to a chat request
-    ChatRequestResponse { accepted: bool, to: PublicKey },
+named_variants! {
+    /// Client websocket event type
+    #[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+    #[serde(rename_all = "PascalCase", tag = "event", content = "data")]
+    pub enum ClientEventType {
+        /// Ping event
+        Ping { timestamp: u64 },
+        /// Pong event
+        Pong { timestamp: u64 },
+        /// Answer to the server hello event, with the client protocol version
+        Hello { version: u16 },
+        /// Acknowledge receiving a server event
+        Ack { id: Uuid },
+        /// Request to chat with a user, the user can be on another homeserver
+        ChatRequest { to: UserAddress },
+        /// Response to a chat request
+        ChatRequestResponse {
+            accepted: bool,
+            to:       UserAddress,
+        },
+        /// Cancel a sent chat request, the user can be on another homeserver
+        CancelChatRequest { to: UserAddress },
+        /// Add a user to the whitelist, and remove them from the blacklist
+        Whitelist { target: PublicKey },
+        /// Add a user to the blacklist, and remove them from the whitelist
+        Blacklist { target: PublicKey },
+        /// Remove a user from the blacklist
+        Unblock { target: PublicKey },
+        /// Remove a user from the whitelist
+        RemoveContact { target: PublicKey },
+        /// Encrypted message to a user, the content is the hex encoded
+        /// ciphertext (encrypted with the shared secret between the sender and
+        /// the recipient)
+        Message { content: String, to: PublicKey },
+    }
 }
 
 impl ClientEventType {
+    /// Returns true if the event can be signed with an ECDSA signature, the
+    /// events that are relayed to another user, so the recipient can verify
+    /// the sender signature end to end
+    pub const fn accepts_ecdsa(&self) -> bool {
+        matches!(self, Self::ChatRequest { .. } | Self::Message { .. })
+    }
+
     /// Returns event data as json bytes
     pub fn data(&self) -> Vec<u8> {
         serde_json::to_value(self).expect("can't fail")["data"]
@@ -56,13 +95,28 @@ impl ClientEventType {
 }
 
 impl ClientEvent {
-    /// Verify the signature of the event
+    /// Verify the signature of the event, the HMAC signature is verified with
+    /// the shared secret, and the ECDSA signature with the sender public key if
+    /// the event accepts it
     pub async fn verify_signature(
         &self,
         shared_secret: &[u8; 32],
+        sender: &PublicKey,
         nonce_cache: &NonceCache,
     ) -> bool {
         utils::is_valid_nonce(&self.signature, nonce_cache).await
-            && self.signature.verify(&self.event.data(), shared_secret)
+            && match self.signature.version() {
+                SignatureVersion::Hmac => self.signature.verify(&self.event.data(), shared_secret),
+                SignatureVersion::Ecdsa => {
+                    self.event.accepts_ecdsa()
+                        && self.signature.verify_ecdsa(&self.event.data(), sender)
+                }
+            }
+    }
+
+    /// Returns the event signature if it's an ECDSA signature, to relay it to
+    /// the recipient
+    pub fn ecdsa_signature(&self) -> Option<Signature> {
+        (self.signature.version() == SignatureVersion::Ecdsa).then_some(self.signature)
     }
 }
@@ -19,48 +19,93 @@
 use std::marker::PhantomData;
 
 use chrono::Utc;
+use oxidetalis_config::Config;
 use oxidetalis_core::{
     cipher::K256Secret,
     types::{PublicKey, Signature},
 };
 use salvo::websocket::Message;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
-use crate::websocket::errors::WsError;
+use super::ClientEventType;
+use crate::{
+    federation::UserAddress,
+    websocket::{errors::WsError, PROTOCOL_VERSION},
+};
 
 /// Signed marker, used to indicate that the event is signed
 pub struct Signed;
 /// Unsigned marker, used to indicate that the event is unsigned
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct Unsigned;
 
 /// Server websocket event
 #[derive(Serialize, Clone, Debug)]
 pub struct ServerEvent<T> {
+    /// The event id, the client acknowledges the event with it
+    id:         Uuid,
+    /// The id of the client event that this event responds to, if the client
+    /// sent it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    request_id: Option<String>,
     #[serde(flatten)]
-    event:     ServerEventType,
-    signature: Signature,
+    event:      ServerEventType,
+    signature:  Signature,
     #[serde(skip)]
-    phantom:   PhantomData<T>,
+    phantom:    PhantomData<T>,
+}
+
+/// The server limits, sent to the client in the hello event
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
+pub struct ServerLimits {
+    /// Maximum size of a single websocket frame sent by the client, in bytes
+    pub max_frame_size:          usize,
+    /// Maximum size of the queued events of an offline user, in bytes
+    pub offline_queue_max_size:  usize,
+    /// Maximum number of events waiting to be sent to a single connection
+    pub outbound_queue_capacity: usize,
 }
 
 /// server websocket event type
-#[derive(Serialize, Clone, Eq, PartialEq, Debug)]
+#[derive(Serialize, Deserialize, Clone, Eq, PartialEq, Debug)]
 #[serde(rename_all = "PascalCase", tag = "event", content = "data")]
 pub enum ServerEventType {
     /// Ping event
     Ping { timestamp: u64 },
     /// Pong event
     Pong { timestamp: u64 },
-    /// New chat request from someone
-    ChatRequest { from: PublicKey },
+    /// Hello event, sent to the client on connect
+    Hello {
+        client_events:    Vec<String>,
+        limits:           ServerLimits,
+        protocol_version: u16,
+        server_name:      String,
+    },
+    /// New chat request from someone, with the sender ECDSA signature of their
+    /// `ChatRequest` event data if they signed it with ECDSA
+    ChatRequest {
+        from:      UserAddress,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<Signature>,
+    },
     /// New chat request response from someone
-    ChatRequestResponse { accepted: bool, from: PublicKey },
-    /// Error event
-    Error {
-        name:   &'static str,
-        reason: &'static str,
+    ChatRequestResponse {
+        accepted: bool,
+        from:     UserAddress,
+    },
+    /// A chat request from someone is cancelled
+    ChatRequestCancelled { from: UserAddress },
+    /// New encrypted message from someone, with the sender ECDSA signature of
+    /// their `Message` event data if they signed it with ECDSA
+    Message {
+        content:   String,
+        from:      PublicKey,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        signature: Option<Signature>,
     },
+    /// Error event
+    Error { name: String, reason: String },
 }
 
 impl ServerEventType {
@@ -76,6 +121,8 @@ impl ServerEvent<Unsigned> {
     /// Creates new [`ServerEvent`]
     pub fn new(event: ServerEventType) -> Self {
         Self {
+            id: Uuid::new_v4(),
+            request_id: None,
             event,
             signature: Signature::from([0u8; 56]),
             phantom: PhantomData,
@@ -96,29 +143,86 @@ impl ServerEvent<Unsigned> {
         })
     }
 
+    /// Creates hello event
+    pub fn hello(config: &Config) -> Self {
+        Self::new(ServerEventType::Hello {
+            client_events:    ClientEventType::NAMES
+                .iter()
+                .map(|name| (*name).to_owned())
+                .collect(),
+            limits:           ServerLimits {
+                max_frame_size:          config.websocket.max_frame_size.as_bytes(),
+                offline_queue_max_size:  config.offline_queue.max_size.as_bytes(),
+                outbound_queue_capacity: config.websocket.outbound_queue_capacity,
+            },
+            protocol_version: PROTOCOL_VERSION,
+            server_name:      config.server.server_name.clone(),
+        })
+    }
+
     /// Create chat request event
-    pub fn chat_request(from: &PublicKey) -> Self {
-        Self::new(ServerEventType::ChatRequest { from: *from })
+    pub fn chat_request(from: impl Into<UserAddress>, signature: Option<Signature>) -> Self {
+        Self::new(ServerEventType::ChatRequest {
+            from: from.into(),
+            signature,
+        })
     }
 
     /// Create chat request response event
-    pub fn chat_request_response(from: PublicKey, accepted: bool) -> Self {
-        Self::new(ServerEventType::ChatRequestResponse { from, accepted })
+    pub fn chat_request_response(from: impl Into<UserAddress>, accepted: bool) -> Self {
+        Self::new(ServerEventType::ChatRequestResponse {
+            from: from.into(),
+            accepted,
+        })
+    }
+
+    /// Create chat request cancelled event
+    pub fn chat_request_cancelled(from: impl Into<UserAddress>) -> Self {
+        Self::new(ServerEventType::ChatRequestCancelled { from: from.into() })
+    }
+
+    /// Create message event
+    pub fn message(from: PublicKey, content: String, signature: Option<Signature>) -> Self {
+        Self::new(ServerEventType::Message {
+            content,
+            from,
+            signature,
+        })
+    }
+
+    /// Sets the id of the client event that this event responds to
+    pub fn with_request_id(mut self, request_id: Option<String>) -> Self {
+        self.request_id = request_id;
+        self
     }
 
     /// Sign the event
     pub fn sign(self, shared_secret: &[u8; 32]) -> ServerEvent<Signed> {
         ServerEvent::<Signed> {
-            signature: K256Secret::sign_with_shared_secret(
+            id:         self.id,
+            request_id: self.request_id,
+            signature:  K256Secret::sign_with_shared_secret(
                 &serde_json::to_vec(&self.event.data()).expect("Can't fail"),
                 shared_secret,
             ),
-            event:     self.event,
-            phantom:   PhantomData,
+            event:      self.event,
+            phantom:    PhantomData,
         }
     }
 }
 
+impl<S> ServerEvent<S> {
+    /// Returns the event id
+    pub const fn id(&self) -> &Uuid {
+        &self.id
+    }
+
+    /// Returns the event type
+    pub const fn event(&self) -> &ServerEventType {
+        &self.event
+    }
+}
+
 impl<S> AsRef<Self> for ServerEvent<S> {
     fn as_ref(&self) -> &Self {
         self
@@ -134,8 +238,8 @@ impl From<&ServerEvent<Signed>> for Message {
 impl From<WsError> for ServerEvent<Unsigned> {
     fn from(err: WsError) -> Self {
         ServerEvent::new(ServerEventType::Error {
-            name:   err.name(),
-            reason: err.reason(),
+            name:   err.name().to_owned(),
+            reason: err.reason().to_owned(),
         })
     }
 }
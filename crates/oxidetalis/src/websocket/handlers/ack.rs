@@ -0,0 +1,46 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Handler for the server events acknowledgement.
+
+use oxidetalis_entities::prelude::*;
+use sea_orm::DatabaseConnection;
+use uuid::Uuid;
+
+use crate::{
+    errors::ServerError,
+    extensions::OnlineUsersExt,
+    try_ws,
+    websocket::{ServerEvent, UnackedEvent, Unsigned, ONLINE_USERS},
+};
+
+/// Handle an acknowledgement of a server event, the acknowledged event will be
+/// removed from the database, unless another connection of the user is still
+/// waiting for its acknowledgement. Unknown event ids are ignored, because the
+/// events that are not stored (e.g. pings) don't need an acknowledgement.
+pub async fn handle_ack(
+    db: &DatabaseConnection,
+    conn_id: &Uuid,
+    event_id: &Uuid,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let delete_result = match ONLINE_USERS.ack_event(conn_id, event_id).await? {
+        UnackedEvent::IncomingChat(incoming_chat) => incoming_chat.delete(db).await,
+        UnackedEvent::QueuedEvent(queued_event) => queued_event.delete(db).await,
+    };
+    try_ws!(Some request_id, delete_result.map_err(ServerError::from));
+    None
+}
@@ -16,31 +16,56 @@
 
 //! Handler for incoming and outgoing chat requests.
 
-use oxidetalis_core::types::PublicKey;
+use oxidetalis_core::types::{PublicKey, Signature};
 use oxidetalis_entities::prelude::*;
 use sea_orm::DatabaseConnection;
 
 use crate::database::IncomingChatExt;
-use crate::errors::ServerError;
+use crate::errors::{ServerError, ServerResult};
 use crate::extensions::OnlineUsersExt;
 use crate::{
-    database::{OutChatRequestsExt, UserTableExt, UsersStatusExt},
+    database::{FederationOutboxExt, OutChatRequestsExt, UserTableExt, UsersStatusExt},
+    federation::{Federation, UserAddress},
+    schemas::FederationEvent,
     try_ws,
-    websocket::{errors::WsError, ServerEvent, Unsigned, ONLINE_USERS},
+    websocket::{errors::WsError, ServerEvent, UnackedEvent, Unsigned, ONLINE_USERS},
 };
 
 /// Handle a chat request from a user.
+///
+/// The sender ECDSA signature is relayed with the chat request, so the
+/// recipient can verify that the sender sent it.
 #[logcall::logcall]
 pub async fn handle_chat_request(
     db: &DatabaseConnection,
+    federation: &Federation,
     chat_request_sender: Option<&UserModel>,
-    chat_request_recipient: &PublicKey,
+    chat_request_recipient: &UserAddress,
+    sender_signature: Option<Signature>,
+    request_id: Option<&str>,
 ) -> Option<ServerEvent<Unsigned>> {
     let Some(chat_request_sender) = chat_request_sender else {
         return Some(WsError::RegistredUserEvent.into());
     };
+    if let Some(server) = chat_request_recipient
+        .server
+        .as_deref()
+        .filter(|server| !federation.is_local_server(server))
+    {
+        return handle_remote_chat_request(
+            db,
+            federation,
+            chat_request_sender,
+            &chat_request_recipient.public_key,
+            server,
+            sender_signature,
+            request_id,
+        )
+        .await;
+    }
+    let chat_request_recipient = &chat_request_recipient.public_key;
     let Some(chat_request_recipient) =
-        try_ws!(Some db.get_user_by_pubk(chat_request_recipient).await)
+        try_ws!(Some request_id, db.get_user_by_pubk(chat_request_recipient).await)
     else {
         return Some(WsError::UserNotFound.into());
     };
@@ -48,11 +73,11 @@ pub async fn handle_chat_request(
         return Some(WsError::CannotSendChatRequestToSelf.into());
     }
 
-    if try_ws!(Some db.get_chat_request_to(chat_request_sender, &chat_request_recipient.public_key).await).is_some() {
+    if try_ws!(Some request_id, db.get_chat_request_to(chat_request_sender, &chat_request_recipient.public_key).await).is_some() {
         return Some(WsError::AlreadySendChatRequest.into());
     }
 
-    if try_ws!(Some db.is_blacklisted(&chat_request_recipient, &chat_request_sender.public_key).await)
+    if try_ws!(Some request_id, db.is_blacklisted(&chat_request_recipient, &chat_request_sender.public_key).await)
     {
         return Some(WsError::RecipientBlacklist.into());
     }
@@ -66,25 +91,22 @@ pub async fn handle_chat_request(
         return Some(WsError::InternalServerError.into());
     }
 
-    if try_ws!(Some db.is_whitelisted(&chat_request_recipient, &chat_request_sender.public_key).await)
+    if try_ws!(Some request_id, db.is_whitelisted(&chat_request_recipient, &chat_request_sender.public_key).await)
     {
         return Some(WsError::AlreadyInRecipientWhitelist.into());
     }
 
-    try_ws!(Some db.save_out_chat_request(chat_request_sender, &chat_request_recipient.public_key).await);
+    try_ws!(Some request_id, db.save_out_chat_request(chat_request_sender, &chat_request_recipient.public_key.into()).await);
 
-    if let Some(conn_id) = ONLINE_USERS
-        .is_online(&chat_request_recipient.public_key)
-        .await
+    if let Some(incoming_chat) = try_ws!(Some request_id, db.save_in_chat_request(&chat_request_recipient, &chat_request_sender.public_key.into(), sender_signature).await)
     {
         ONLINE_USERS
-            .send(
-                &conn_id,
-                ServerEvent::chat_request(&chat_request_sender.public_key),
+            .send_stored_to_user(
+                &chat_request_recipient.public_key,
+                &ServerEvent::chat_request(chat_request_sender.public_key, sender_signature),
+                UnackedEvent::IncomingChat(incoming_chat),
             )
             .await;
-    } else {
-        try_ws!(Some db.save_in_chat_request(&chat_request_recipient, &chat_request_sender.public_key).await);
     }
     None
 }
@@ -92,15 +114,35 @@ pub async fn handle_chat_request(
 #[logcall::logcall]
 pub async fn handle_chat_response(
     db: &DatabaseConnection,
+    federation: &Federation,
     response_sender: Option<&UserModel>,
-    response_recipient: &PublicKey,
+    response_recipient: &UserAddress,
     accepted: bool,
+    request_id: Option<&str>,
 ) -> Option<ServerEvent<Unsigned>> {
     let Some(response_sender) = response_sender else {
         return Some(WsError::RegistredUserEvent.into());
     };
+    if let Some(server) = response_recipient
+        .server
+        .as_deref()
+        .filter(|server| !federation.is_local_server(server))
+    {
+        return handle_remote_chat_response(
+            db,
+            federation,
+            response_sender,
+            &response_recipient.public_key,
+            server,
+            accepted,
+            request_id,
+        )
+        .await;
+    }
+    let response_recipient = &response_recipient.public_key;
 
-    let Some(response_recipient) = try_ws!(Some db.get_user_by_pubk(response_recipient).await)
+    let Some(response_recipient) =
+        try_ws!(Some request_id, db.get_user_by_pubk(response_recipient).await)
     else {
         return Some(WsError::UserNotFound.into());
     };
@@ -109,10 +151,12 @@ pub async fn handle_chat_response(
         return Some(WsError::CannotRespondToOwnChatRequest.into());
     }
 
-    if try_ws!(Some
+    // The requests to the remote users are answered by their homeservers
+    if try_ws!(Some request_id,
         db.get_chat_request_to(&response_recipient, &response_sender.public_key)
             .await
     )
+    .filter(|request| request.recipient_server.is_none())
     .is_none()
     {
         return Some(WsError::NoChatRequestFromRecipient.into());
@@ -128,23 +172,196 @@ pub async fn handle_chat_response(
             .await
     };
 
-    try_ws!(Some
+    try_ws!(Some request_id,
         db.remove_out_chat_request(&response_recipient, &response_sender.public_key)
             .await
     );
 
-    if let Some(conn_id) = ONLINE_USERS.is_online(&response_recipient.public_key).await {
+    if let Some(incoming_chat) = try_ws!(Some request_id,
+        db.save_in_chat_response(&response_recipient, &response_sender.public_key.into(), accepted).await
+    ) {
         ONLINE_USERS
-            .send(
-                &conn_id,
-                ServerEvent::chat_request_response(response_sender.public_key, accepted),
+            .send_stored_to_user(
+                &response_recipient.public_key,
+                &ServerEvent::chat_request_response(response_sender.public_key, accepted),
+                UnackedEvent::IncomingChat(incoming_chat),
             )
             .await;
+    }
+
+    None
+}
+
+/// Handle a chat request to a user on another homeserver, the request is saved
+/// then appended to the federation outbox to be delivered to their homeserver,
+/// the sender is notified if it's not delivered.
+async fn handle_remote_chat_request(
+    db: &DatabaseConnection,
+    federation: &Federation,
+    chat_request_sender: &UserModel,
+    chat_request_recipient: &PublicKey,
+    recipient_server: &str,
+    sender_signature: Option<Signature>,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    if try_ws!(Some request_id, db.get_chat_request_to(chat_request_sender, chat_request_recipient).await)
+        .is_some()
+    {
+        return Some(WsError::AlreadySendChatRequest.into());
+    }
+
+    let event = FederationEvent::ChatRequest {
+        from:      chat_request_sender.public_key,
+        to:        *chat_request_recipient,
+        signature: sender_signature,
+    };
+    // To ignore the error if the requester added the recipient to the whitelist
+    // table before send a request to them
+    if let Err(ServerError::Internal(_)) = db
+        .add_to_whitelist(chat_request_sender, chat_request_recipient)
+        .await
+    {
+        return Some(WsError::InternalServerError.into());
+    }
+    try_ws!(Some request_id,
+        db.save_out_chat_request(
+            chat_request_sender,
+            &UserAddress::new(*chat_request_recipient, Some(recipient_server.to_owned())),
+        )
+        .await
+    );
+    try_ws!(Some request_id,
+        queue_federation_event(db, federation, chat_request_sender, recipient_server, &event, request_id).await
+    );
+    None
+}
+
+/// Handle a response to a chat request from a user on another homeserver, the
+/// response is appended to the federation outbox to be delivered to their
+/// homeserver, which checks that the chat request exists, the sender is
+/// notified if it's not delivered.
+async fn handle_remote_chat_response(
+    db: &DatabaseConnection,
+    federation: &Federation,
+    response_sender: &UserModel,
+    response_recipient: &PublicKey,
+    recipient_server: &str,
+    accepted: bool,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let event = FederationEvent::ChatRequestResponse {
+        accepted,
+        from: response_sender.public_key,
+        to: *response_recipient,
+    };
+    // We don't need to handle the case where the sender is blacklisted or
+    // whitelisted already, just add it if it is not already there
+    let _ = if accepted {
+        db.add_to_whitelist(response_sender, response_recipient)
+            .await
     } else {
-        try_ws!(Some
-            db.save_in_chat_response(&response_recipient, &response_sender.public_key, accepted).await
-        );
+        db.add_to_blacklist(response_sender, response_recipient)
+            .await
+    };
+    try_ws!(Some request_id,
+        queue_federation_event(db, federation, response_sender, recipient_server, &event, request_id).await
+    );
+    None
+}
+
+/// Handle cancelling a sent chat request
+#[logcall::logcall]
+pub async fn handle_cancel_chat_request(
+    db: &DatabaseConnection,
+    federation: &Federation,
+    chat_request_sender: Option<&UserModel>,
+    chat_request_recipient: &UserAddress,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let Some(chat_request_sender) = chat_request_sender else {
+        return Some(WsError::RegistredUserEvent.into());
+    };
+    if let Some(server) = chat_request_recipient
+        .server
+        .as_deref()
+        .filter(|server| !federation.is_local_server(server))
+    {
+        return handle_remote_cancel_chat_request(
+            db,
+            federation,
+            chat_request_sender,
+            &chat_request_recipient.public_key,
+            server,
+            request_id,
+        )
+        .await;
     }
+    let chat_request_recipient = &chat_request_recipient.public_key;
+    let Some(chat_request_recipient) =
+        try_ws!(Some request_id, db.get_user_by_pubk(chat_request_recipient).await)
+    else {
+        return Some(WsError::UserNotFound.into());
+    };
+
+    if try_ws!(Some request_id, db.get_chat_request_to(chat_request_sender, &chat_request_recipient.public_key).await).is_none() {
+        return Some(WsError::NoChatRequestToRecipient.into());
+    }
+
+    try_ws!(Some request_id, db.remove_out_chat_request(chat_request_sender, &chat_request_recipient.public_key).await);
+    try_ws!(Some request_id, db.remove_in_chat_request(&chat_request_recipient, &chat_request_sender.public_key.into()).await);
 
+    ONLINE_USERS
+        .send_to_user(
+            &chat_request_recipient.public_key,
+            &ServerEvent::chat_request_cancelled(chat_request_sender.public_key),
+        )
+        .await;
     None
 }
+
+/// Handle cancelling a chat request sent to a user on another homeserver, the
+/// request is cancelled locally, then the cancellation is appended to the
+/// federation outbox to be delivered to their homeserver.
+async fn handle_remote_cancel_chat_request(
+    db: &DatabaseConnection,
+    federation: &Federation,
+    chat_request_sender: &UserModel,
+    chat_request_recipient: &PublicKey,
+    recipient_server: &str,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    if try_ws!(Some request_id, db.get_chat_request_to(chat_request_sender, chat_request_recipient).await)
+        .is_none()
+    {
+        return Some(WsError::NoChatRequestToRecipient.into());
+    }
+    try_ws!(Some request_id, db.remove_out_chat_request(chat_request_sender, chat_request_recipient).await);
+
+    let event = FederationEvent::CancelChatRequest {
+        from: chat_request_sender.public_key,
+        to:   *chat_request_recipient,
+    };
+    try_ws!(Some request_id,
+        queue_federation_event(db, federation, chat_request_sender, recipient_server, &event, request_id).await
+    );
+    None
+}
+
+/// Append the event to the federation outbox and wake the outbox worker up.
+///
+/// The remote homeservers are not requested from the websocket handlers, the
+/// outbox worker delivers the events to each homeserver in order and notifies
+/// the sender if an event is not delivered.
+async fn queue_federation_event(
+    db: &DatabaseConnection,
+    federation: &Federation,
+    sender: &UserModel,
+    server: &str,
+    event: &FederationEvent,
+    request_id: Option<&str>,
+) -> ServerResult<()> {
+    db.push_federation_event(sender, server, event, request_id.map(ToOwned::to_owned))
+        .await?;
+    federation.wake_outbox();
+    Ok(())
+}
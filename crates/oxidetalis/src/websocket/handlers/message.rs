@@ -0,0 +1,81 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Handler for end-to-end encrypted messages between users.
+
+use oxidetalis_config::OfflineQueue;
+use oxidetalis_core::types::{PublicKey, Signature};
+use oxidetalis_entities::prelude::*;
+use sea_orm::DatabaseConnection;
+
+use crate::{
+    database::{QueuedEventsExt, UserTableExt, UsersStatusExt},
+    extensions::OnlineUsersExt,
+    try_ws,
+    websocket::{errors::WsError, ServerEvent, UnackedEvent, Unsigned, ONLINE_USERS},
+};
+
+/// Handle a message from a user, relay it to the recipient.
+///
+/// The message content is encrypted by the sender, the server can't read it,
+/// it will only relay it if the sender and the recipient are on each other's
+/// whitelist. The message is queued until the recipient acknowledges it, so
+/// it's delivered in their next connection if they are offline. The sender
+/// ECDSA signature is relayed with the message, so the recipient can verify
+/// that the sender wrote it.
+#[logcall::logcall]
+pub async fn handle_message(
+    db: &DatabaseConnection,
+    queue_config: &OfflineQueue,
+    message_sender: Option<&UserModel>,
+    message_recipient: &PublicKey,
+    content: &str,
+    sender_signature: Option<Signature>,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let Some(message_sender) = message_sender else {
+        return Some(WsError::RegistredUserEvent.into());
+    };
+    let Some(message_recipient) =
+        try_ws!(Some request_id, db.get_user_by_pubk(message_recipient).await)
+    else {
+        return Some(WsError::UserNotFound.into());
+    };
+    if message_sender.id == message_recipient.id {
+        return Some(WsError::CannotSendMessageToSelf.into());
+    }
+
+    if !try_ws!(Some request_id, db.is_whitelisted(message_sender, &message_recipient.public_key).await)
+        || !try_ws!(Some request_id, db.is_whitelisted(&message_recipient, &message_sender.public_key).await)
+    {
+        return Some(WsError::NotMutuallyWhitelisted.into());
+    }
+
+    let message = ServerEvent::message(
+        message_sender.public_key,
+        content.to_owned(),
+        sender_signature,
+    );
+    let queued_event = try_ws!(Some request_id, db.queue_event(&message_recipient, message.event(), queue_config).await);
+    ONLINE_USERS
+        .send_stored_to_user(
+            &message_recipient.public_key,
+            &message,
+            UnackedEvent::QueuedEvent(queued_event),
+        )
+        .await;
+    None
+}
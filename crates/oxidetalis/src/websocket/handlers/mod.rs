@@ -16,6 +16,12 @@
 
 //! Websocket event handlers.
 
+mod ack;
 mod chat_request;
+mod message;
+mod users_status;
 
+pub use ack::*;
 pub use chat_request::*;
+pub use message::*;
+pub use users_status::*;
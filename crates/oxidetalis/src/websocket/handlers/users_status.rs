@@ -0,0 +1,93 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Handler for managing the whitelist and the blacklist of a user.
+
+use oxidetalis_core::types::PublicKey;
+use oxidetalis_entities::prelude::*;
+use sea_orm::DatabaseConnection;
+
+use crate::{
+    database::UsersStatusExt,
+    try_ws,
+    websocket::{errors::WsError, ServerEvent, Unsigned},
+};
+
+/// Handle adding a user to the whitelist.
+#[logcall::logcall]
+pub async fn handle_whitelist(
+    db: &DatabaseConnection,
+    whitelister: Option<&UserModel>,
+    target: &PublicKey,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let Some(whitelister) = whitelister else {
+        return Some(WsError::RegistredUserEvent.into());
+    };
+    try_ws!(Some request_id, db.add_to_whitelist(whitelister, target).await);
+    None
+}
+
+/// Handle adding a user to the blacklist.
+#[logcall::logcall]
+pub async fn handle_blacklist(
+    db: &DatabaseConnection,
+    blacklister: Option<&UserModel>,
+    target: &PublicKey,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let Some(blacklister) = blacklister else {
+        return Some(WsError::RegistredUserEvent.into());
+    };
+    try_ws!(Some request_id, db.add_to_blacklist(blacklister, target).await);
+    None
+}
+
+/// Handle removing a user from the blacklist.
+#[logcall::logcall]
+pub async fn handle_unblock(
+    db: &DatabaseConnection,
+    blacklister: Option<&UserModel>,
+    target: &PublicKey,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let Some(blacklister) = blacklister else {
+        return Some(WsError::RegistredUserEvent.into());
+    };
+    if !try_ws!(Some request_id, db.is_blacklisted(blacklister, target).await) {
+        return Some(WsError::NotOnTheBlacklist.into());
+    }
+    try_ws!(Some request_id, db.remove_from_blacklist(blacklister, target).await);
+    None
+}
+
+/// Handle removing a user from the whitelist.
+#[logcall::logcall]
+pub async fn handle_remove_contact(
+    db: &DatabaseConnection,
+    whitelister: Option<&UserModel>,
+    target: &PublicKey,
+    request_id: Option<&str>,
+) -> Option<ServerEvent<Unsigned>> {
+    let Some(whitelister) = whitelister else {
+        return Some(WsError::RegistredUserEvent.into());
+    };
+    if !try_ws!(Some request_id, db.is_whitelisted(whitelister, target).await) {
+        return Some(WsError::NotOnTheWhitelist.into());
+    }
+    try_ws!(Some request_id, db.remove_from_whitelist(whitelister, target).await);
+    None
+}
@@ -0,0 +1,83 @@
+// OxideTalis Messaging Protocol homeserver implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// This program is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as
+// published by the Free Software Foundation, either version 3 of the
+// License, or (at your option) any later version.
+//
+// This program is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU Affero General Public License for more details.
+//
+// You should have received a copy of the GNU Affero General Public License
+// along with this program. If not, see <https://gnu.org/licenses/agpl-3.0>.
+
+//! Per-connection keepalive, each connection pings on its own jittered timer,
+//! and it's dropped if the pong didn't arrive in time.
+
+use std::time::Duration;
+
+use oxidetalis_config::Websocket;
+use rand::Rng;
+use tokio::time::{sleep_until as tokio_sleep_until, Instant};
+
+/// The maximum jitter of the ping interval, as a fraction of the interval
+const PING_JITTER: f64 = 0.1;
+
+/// The action that should be taken when the keepalive timer fires
+pub enum KeepAliveAction {
+    /// Ping the connection
+    Ping,
+    /// Check if the connection responded to the ping
+    CheckPong,
+}
+
+/// Keepalive timer of a connection
+pub struct KeepAlive {
+    /// Time between the pings
+    ping_interval:    Duration,
+    /// Time to wait for the pong
+    pong_timeout:     Duration,
+    /// When the timer fires next
+    deadline:         Instant,
+    /// Whether the connection is pinged and the pong is awaited
+    waiting_for_pong: bool,
+}
+
+impl KeepAlive {
+    /// Creates a new [`KeepAlive`], the first ping will be after a jittered
+    /// ping interval
+    pub fn new(config: &Websocket) -> Self {
+        let ping_interval = Duration::from_secs(config.ping_interval_secs);
+        Self {
+            ping_interval,
+            pong_timeout: Duration::from_secs(config.pong_timeout_secs),
+            deadline: Instant::now() + jittered(ping_interval),
+            waiting_for_pong: false,
+        }
+    }
+
+    /// Wait until the timer fires and returns the action to take.
+    ///
+    /// This is cancel safe, the timer state is only changed after it fires.
+    pub async fn tick(&mut self) -> KeepAliveAction {
+        tokio_sleep_until(self.deadline).await;
+        if self.waiting_for_pong {
+            self.waiting_for_pong = false;
+            self.deadline = Instant::now() + jittered(self.ping_interval);
+            KeepAliveAction::CheckPong
+        } else {
+            self.waiting_for_pong = true;
+            self.deadline = Instant::now() + self.pong_timeout;
+            KeepAliveAction::Ping
+        }
+    }
+}
+
+/// Returns the duration with a random jitter, so the connections don't ping at
+/// the same time
+fn jittered(duration: Duration) -> Duration {
+    duration.mul_f64(rand::thread_rng().gen_range(1.0 - PING_JITTER..=1.0 + PING_JITTER))
+}
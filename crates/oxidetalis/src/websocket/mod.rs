@@ -17,12 +17,20 @@
 //! Oxidetalis WebSocket server implementation, handling the WebSocket
 //! connections.
 
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 use chrono::Utc;
-use errors::{WsError, WsResult};
-use futures::{channel::mpsc, FutureExt, StreamExt, TryStreamExt};
+use dashmap::DashMap;
+use errors::WsError;
+use futures::{channel::mpsc, FutureExt, SinkExt, StreamExt, TryStreamExt};
 use once_cell::sync::Lazy;
+use oxidetalis_config::{Config, SlowClientPolicy};
 use oxidetalis_core::types::PublicKey;
 use oxidetalis_entities::prelude::*;
 use salvo::{
@@ -36,59 +44,136 @@ use salvo::{
     Writer,
 };
 use sea_orm::DatabaseConnection;
-use tokio::{sync::RwLock, task::spawn as tokio_spawn, time::sleep as tokio_sleep};
+use tokio::task::spawn as tokio_spawn;
+use zeroize::Zeroizing;
 
 pub mod errors;
 mod events;
 mod handlers;
+mod keepalive;
 
 pub use events::*;
+use keepalive::{KeepAlive, KeepAliveAction};
 use uuid::Uuid;
 
 use crate::{
-    database::{IncomingChatExt, UserTableExt},
+    database::{IncomingChatExt, QueuedEventsExt, UserTableExt},
     extensions::{DepotExt, OnlineUsersExt},
+    federation::{self, Federation, UserAddress},
     middlewares,
     nonce::NonceCache,
 };
 
-/// Online users type
-pub type OnlineUsers = RwLock<HashMap<Uuid, SocketUserData>>;
+/// Events that sent to the user and waiting for their acknowledgement, the key
+/// is the server event id
+pub type UnackedEvents = HashMap<Uuid, UnackedEvent>;
+
+/// Online users registry, the connections are stored in sharded maps so
+/// the connections don't contend on a single lock
+#[derive(Default)]
+pub struct OnlineUsers {
+    /// The online connections, the key is the connection id
+    pub connections:   DashMap<Uuid, SocketUserData>,
+    /// The connections ids of each online user
+    pub by_public_key: DashMap<PublicKey, HashSet<Uuid>>,
+}
 
 /// List of online users, users that are connected to the server
 // FIXME: Use `std::sync::LazyLock` after it becomes stable in `1.80.0`
-static ONLINE_USERS: Lazy<OnlineUsers> = Lazy::new(OnlineUsers::default);
+pub(crate) static ONLINE_USERS: Lazy<OnlineUsers> = Lazy::new(OnlineUsers::default);
+
+/// The OTMP websocket protocol version that the server speaks, clients with a
+/// different version are rejected
+///
+/// Version 2 signs the events and the canonical requests with the HKDF derived
+/// client-server key instead of the raw shared secret
+pub const PROTOCOL_VERSION: u16 = 2;
+
+/// Number of times a connection couldn't keep up with the server events (its
+/// outbound queue was full)
+static SLOW_CLIENT_EVENTS: AtomicU64 = AtomicU64::new(0);
+
+/// An event stored in the database, sent to the user and waiting for their
+/// acknowledgement before deleting it from the database
+#[derive(Clone, PartialEq)]
+pub enum UnackedEvent {
+    /// Incoming chat request or response
+    IncomingChat(IncomingChatModel),
+    /// Queued event
+    QueuedEvent(QueuedEventsModel),
+}
 
 /// A user connected to the server
 pub struct SocketUserData {
-    /// Sender to send messages to the user
-    pub sender:        mpsc::UnboundedSender<salvo::Result<Message>>,
+    /// Sender to send messages to the user, bounded by the outbound queue
+    /// capacity
+    pub sender:             mpsc::Sender<salvo::Result<Message>>,
     /// User public key
-    pub public_key:    PublicKey,
+    pub public_key:         PublicKey,
     /// Time that the user pinged at
-    pub pinged_at:     chrono::DateTime<Utc>,
+    pub pinged_at:          chrono::DateTime<Utc>,
     /// Time that the user ponged at
-    pub ponged_at:     chrono::DateTime<Utc>,
-    /// User shared secret
-    pub shared_secret: [u8; 32],
+    pub ponged_at:          chrono::DateTime<Utc>,
+    /// User shared secret, shared with the connection task and zeroized when
+    /// the connection is closed
+    pub shared_secret:      Arc<Zeroizing<[u8; 32]>>,
+    /// The policy applied when the user outbound queue is full
+    pub slow_client_policy: SlowClientPolicy,
+    /// The stored events that sent to this connection and waiting for the
+    /// user acknowledgement
+    pub unacked_events:     UnackedEvents,
 }
 
 impl SocketUserData {
     /// Creates new [`SocketUserData`]
     pub fn new(
         public_key: PublicKey,
-        shared_secret: [u8; 32],
-        sender: mpsc::UnboundedSender<salvo::Result<Message>>,
+        shared_secret: Arc<Zeroizing<[u8; 32]>>,
+        sender: mpsc::Sender<salvo::Result<Message>>,
+        slow_client_policy: SlowClientPolicy,
     ) -> Self {
         let now = Utc::now();
         Self {
             sender,
             public_key,
             shared_secret,
+            slow_client_policy,
+            unacked_events: UnackedEvents::new(),
             pinged_at: now,
             ponged_at: now,
         }
     }
+
+    /// Try to send the event to the user without waiting, returns `true` if
+    /// the event is sent.
+    ///
+    /// If the user outbound queue is full the slow client policy will be
+    /// applied, [`SlowClientPolicy::Disconnect`] will close the connection, and
+    /// [`SlowClientPolicy::Spill`] will keep it. In both cases the user only
+    /// gets the undelivered event in their next connection if the event is
+    /// stored in the database.
+    pub fn try_send(&mut self, conn_id: &Uuid, event: ServerEvent<Unsigned>) -> bool {
+        match self
+            .sender
+            .try_send(Ok(event.sign(&self.shared_secret).as_ref().into()))
+        {
+            Ok(()) => true,
+            Err(err) if err.is_full() => {
+                let slow_client_events = SLOW_CLIENT_EVENTS.fetch_add(1, Ordering::Relaxed) + 1;
+                log::warn!(
+                    "Slow client: ConnId(={conn_id}) PublicKey(={}) Policy(={}) \
+                     TotalSlowClientEvents(={slow_client_events})",
+                    self.public_key,
+                    self.slow_client_policy
+                );
+                if matches!(self.slow_client_policy, SlowClientPolicy::Disconnect) {
+                    self.sender.close_channel();
+                }
+                false
+            }
+            Err(_) => false,
+        }
+    }
 }
 
 /// WebSocket handler, that handles the user connection
@@ -101,11 +186,27 @@ pub async fn user_connected(
 ) -> Result<(), StatusError> {
     let nonce_cache = depot.nonce_cache();
     let db_conn = depot.db_conn();
-    let shared_secret = depot.config().server.private_key.shared_secret(&public_key);
+    let config = depot.config();
+    let federation = depot.federation();
+    let shared_secret = Arc::new(
+        depot
+            .secret_cache()
+            .auth_key(&config.server.private_key, &public_key)
+            .await,
+    );
 
     WebSocketUpgrade::new()
+        .max_frame_size(config.websocket.max_frame_size.as_bytes())
         .upgrade(req, res, move |ws| {
-            handle_socket(ws, db_conn, nonce_cache, public_key, shared_secret)
+            handle_socket(
+                ws,
+                db_conn,
+                config,
+                federation,
+                nonce_cache,
+                public_key,
+                shared_secret,
+            )
         })
         .await
 }
@@ -114,59 +215,137 @@ pub async fn user_connected(
 async fn handle_socket(
     ws: WebSocket,
     db_conn: Arc<DatabaseConnection>,
+    config: Arc<Config>,
+    federation: Arc<Federation>,
     nonce_cache: Arc<NonceCache>,
     user_public_key: PublicKey,
-    user_shared_secret: [u8; 32],
+    user_shared_secret: Arc<Zeroizing<[u8; 32]>>,
 ) {
     let (user_ws_sender, mut user_ws_receiver) = ws.split();
 
-    let (sender, receiver) = mpsc::unbounded();
+    let (mut sender, receiver) = mpsc::channel(config.websocket.outbound_queue_capacity);
     let receiver = receiver.into_stream();
     let fut = receiver.forward(user_ws_sender).map(|result| {
         if let Err(err) = result {
             log::error!("websocket send error: {err}");
         }
     });
-    tokio_spawn(fut);
+    let mut forward_task = tokio_spawn(fut);
     let conn_id = Uuid::new_v4();
     let Ok(user) = db_conn.get_user_by_pubk(&user_public_key).await else {
-        let _ = sender.unbounded_send(Ok(ServerEvent::from(WsError::InternalServerError)
-            .sign(&user_shared_secret)
-            .as_ref()
-            .into()));
+        let _ = sender
+            .send(Ok(ServerEvent::from(WsError::InternalServerError)
+                .sign(&user_shared_secret)
+                .as_ref()
+                .into()))
+            .await;
         return;
     };
     ONLINE_USERS
         .add_user(
             &conn_id,
-            SocketUserData::new(user_public_key, user_shared_secret, sender.clone()),
+            SocketUserData::new(
+                user_public_key,
+                Arc::clone(&user_shared_secret),
+                sender.clone(),
+                config.websocket.slow_client_policy,
+            ),
         )
         .await;
     log::info!("New user connected: ConnId(={conn_id}) PublicKey(={user_public_key})");
+    if sender
+        .send(Ok(ServerEvent::hello(&config)
+            .sign(&user_shared_secret)
+            .as_ref()
+            .into()))
+        .await
+        .is_err()
+    {
+        user_disconnected(&db_conn, &conn_id, &user_public_key, user).await;
+        return;
+    }
 
     if let Some(server_user) = &user {
-        send_chat_requests_and_responses(&db_conn, &user_shared_secret, &sender, server_user).await;
+        send_chat_requests_and_responses(
+            &db_conn,
+            &conn_id,
+            &user_shared_secret,
+            &mut sender,
+            server_user,
+        )
+        .await;
+        send_queued_events(
+            &db_conn,
+            &config,
+            &conn_id,
+            &user_shared_secret,
+            &mut sender,
+            server_user,
+        )
+        .await;
     }
 
-    while let Some(Ok(msg)) = user_ws_receiver.next().await {
-        match handle_ws_msg(msg, &nonce_cache, &user_shared_secret).await {
+    let mut keepalive = KeepAlive::new(&config.websocket);
+    loop {
+        let msg = tokio::select! {
+            msg = user_ws_receiver.next() => msg,
+            // The outbound channel is closed (e.g. the user is banned), or the
+            // connection is broken
+            _ = &mut forward_task => break,
+            action = keepalive.tick() => {
+                match action {
+                    KeepAliveAction::Ping => {
+                        if !ONLINE_USERS.ping(&conn_id).await {
+                            break;
+                        }
+                    }
+                    KeepAliveAction::CheckPong => {
+                        if ONLINE_USERS.is_inactive(&conn_id).await {
+                            log::info!("Disconnected from ConnId(={conn_id}), inactive");
+                            sender.close_channel();
+                            break;
+                        }
+                    }
+                }
+                continue;
+            }
+        };
+        let Some(Ok(msg)) = msg else {
+            break;
+        };
+        match handle_ws_msg(msg, &nonce_cache, &user_shared_secret, &user_public_key).await {
             Ok(event) => {
-                if let Some(server_event) =
-                    handle_events(event, &db_conn, &conn_id, user.as_ref()).await
+                let is_incompatible_client = matches!(
+                    event.event,
+                    ClientEventType::Hello { version } if version != PROTOCOL_VERSION
+                );
+                if let Some(server_event) = handle_events(
+                    event,
+                    &db_conn,
+                    &config,
+                    &federation,
+                    &conn_id,
+                    user.as_ref(),
+                )
+                .await
                 {
                     if let Err(err) = sender
-                        .unbounded_send(Ok(server_event.sign(&user_shared_secret).as_ref().into()))
+                        .send(Ok(server_event.sign(&user_shared_secret).as_ref().into()))
+                        .await
                     {
                         log::error!("Websocket Error: {err}");
                         break;
                     }
                 };
+                if is_incompatible_client {
+                    log::info!("Disconnected from ConnId(={conn_id}), incompatible client");
+                    break;
+                }
             }
-            Err(err) => {
-                if let Err(err) = sender.unbounded_send(Ok(ServerEvent::from(err)
-                    .sign(&user_shared_secret)
-                    .as_ref()
-                    .into()))
+            Err(error_event) => {
+                if let Err(err) = sender
+                    .send(Ok(error_event.sign(&user_shared_secret).as_ref().into()))
+                    .await
                 {
                     log::error!("Websocket Error: {err}");
                     break;
@@ -184,12 +363,14 @@ async fn handle_socket(
 /// The errors are ignored, if there is an issue with user connection, the user
 /// will be disconnected after this function is called.
 ///
-/// Also we make sure that the user receives the chat requests and responses
-/// before we delete them from the database.
+/// The chat requests and responses will not be deleted from the database until
+/// the user acknowledges them, the unacknowledged ones will be sent again in
+/// the next connection.
 async fn send_chat_requests_and_responses(
     db_conn: &DatabaseConnection,
+    conn_id: &Uuid,
     user_shared_secret: &[u8; 32],
-    sender: &mpsc::UnboundedSender<Result<Message, salvo::Error>>,
+    sender: &mut mpsc::Sender<Result<Message, salvo::Error>>,
     server_user: &UserModel,
 ) {
     let Ok(requests) = db_conn.get_all_chat_requests(server_user).await else {
@@ -199,52 +380,104 @@ async fn send_chat_requests_and_responses(
         return;
     };
 
-    for request in requests {
+    for incoming_chat in requests.into_iter().chain(responses) {
+        let chat_sender =
+            UserAddress::new(incoming_chat.sender, incoming_chat.sender_server.clone());
+        let event = incoming_chat.accepted_response.map_or_else(
+            || ServerEvent::chat_request(chat_sender.clone(), incoming_chat.signature),
+            |accepted| ServerEvent::chat_request_response(chat_sender.clone(), accepted),
+        );
+        let event_id = *event.id();
         if sender
-            .unbounded_send(Ok(ServerEvent::chat_request(&request.sender)
-                .sign(user_shared_secret)
-                .as_ref()
-                .into()))
-            .is_ok()
+            .send(Ok(event.sign(user_shared_secret).as_ref().into()))
+            .await
+            .is_err()
         {
-            let _ = request.delete(db_conn).await;
+            break;
         }
+        ONLINE_USERS
+            .add_unacked(conn_id, event_id, UnackedEvent::IncomingChat(incoming_chat))
+            .await;
     }
+}
+
+/// Send the queued events to the user, the events that were sent to them while
+/// they were offline, in the same order they were queued
+///
+/// ### Note
+/// The errors are ignored, same as [`send_chat_requests_and_responses`], and
+/// the events will not be deleted from the database until the user
+/// acknowledges them.
+async fn send_queued_events(
+    db_conn: &DatabaseConnection,
+    config: &Config,
+    conn_id: &Uuid,
+    user_shared_secret: &[u8; 32],
+    sender: &mut mpsc::Sender<Result<Message, salvo::Error>>,
+    server_user: &UserModel,
+) {
+    let Ok(events) = db_conn
+        .get_queued_events(server_user, &config.offline_queue)
+        .await
+    else {
+        return;
+    };
 
-    for response in responses {
+    for queued_event in events {
+        let event = match serde_json::from_str::<ServerEventType>(&queued_event.payload) {
+            Ok(event) => ServerEvent::new(event),
+            Err(err) => {
+                log::error!("Invalid queued event, it will be dropped: {err}");
+                let _ = queued_event.delete(db_conn).await;
+                continue;
+            }
+        };
+        let event_id = *event.id();
         if sender
-            .unbounded_send(Ok(ServerEvent::chat_request_response(
-                response.sender,
-                response.accepted_response.unwrap_or_default(),
-            )
-            .sign(user_shared_secret)
-            .as_ref()
-            .into()))
-            .is_ok()
+            .send(Ok(event.sign(user_shared_secret).as_ref().into()))
+            .await
+            .is_err()
         {
-            let _ = response.delete(db_conn).await;
+            break;
         }
+        ONLINE_USERS
+            .add_unacked(conn_id, event_id, UnackedEvent::QueuedEvent(queued_event))
+            .await;
     }
 }
 
-/// Handle websocket msg
+/// Handle websocket msg, returns the error event if the message is invalid
 async fn handle_ws_msg(
     msg: Message,
     nonce_cache: &NonceCache,
     shared_secret: &[u8; 32],
-) -> WsResult<ClientEvent> {
+    public_key: &PublicKey,
+) -> Result<ClientEvent, ServerEvent<Unsigned>> {
     let Ok(text) = msg.to_str() else {
-        return Err(WsError::NotTextMessage);
+        return Err(WsError::NotTextMessage.into());
     };
     let event = serde_json::from_str::<ClientEvent>(text).map_err(|err| {
-        if err.is_data() {
+        let err = if err.is_data() {
             WsError::UnknownClientEvent
         } else {
             WsError::InvalidJsonData
-        }
+        };
+        // Echo the request id if the client sent it, even if the event is invalid
+        let request_id = serde_json::from_str::<ClientEventId>(text)
+            .ok()
+            .and_then(|event_id| event_id.id);
+        ServerEvent::from(err).with_request_id(request_id)
     })?;
-    if !event.verify_signature(shared_secret, nonce_cache).await {
-        return Err(WsError::InvalidSignature);
+    // Clients of another protocol version sign with other keys, their hello
+    // event is passed as is to reply with `IncompatibleClient` and disconnect
+    if matches!(event.event, ClientEventType::Hello { version } if version != PROTOCOL_VERSION) {
+        return Ok(event);
+    }
+    if !event
+        .verify_signature(shared_secret, public_key, nonce_cache)
+        .await
+    {
+        return Err(ServerEvent::from(WsError::InvalidSignature).with_request_id(event.id));
     }
     Ok(event)
 }
@@ -253,20 +486,68 @@ async fn handle_ws_msg(
 async fn handle_events(
     event: ClientEvent,
     db: &DatabaseConnection,
+    config: &Config,
+    federation: &Federation,
     conn_id: &Uuid,
     user: Option<&UserModel>,
 ) -> Option<ServerEvent<Unsigned>> {
-    match &event.event {
+    let server_event = match &event.event {
         ClientEventType::Ping { .. } => Some(ServerEvent::pong()),
         ClientEventType::Pong { .. } => {
             ONLINE_USERS.update_pong(conn_id).await;
             None
         }
-        ClientEventType::ChatRequest { to } => handlers::handle_chat_request(db, user, to).await,
+        ClientEventType::ChatRequest { to } => {
+            handlers::handle_chat_request(
+                db,
+                federation,
+                user,
+                to,
+                event.ecdsa_signature(),
+                event.id.as_deref(),
+            )
+            .await
+        }
         ClientEventType::ChatRequestResponse { to, accepted } => {
-            handlers::handle_chat_response(db, user, to, *accepted).await
+            handlers::handle_chat_response(db, federation, user, to, *accepted, event.id.as_deref())
+                .await
         }
-    }
+        ClientEventType::CancelChatRequest { to } => {
+            handlers::handle_cancel_chat_request(db, federation, user, to, event.id.as_deref())
+                .await
+        }
+        ClientEventType::Whitelist { target } => {
+            handlers::handle_whitelist(db, user, target, event.id.as_deref()).await
+        }
+        ClientEventType::Blacklist { target } => {
+            handlers::handle_blacklist(db, user, target, event.id.as_deref()).await
+        }
+        ClientEventType::Unblock { target } => {
+            handlers::handle_unblock(db, user, target, event.id.as_deref()).await
+        }
+        ClientEventType::RemoveContact { target } => {
+            handlers::handle_remove_contact(db, user, target, event.id.as_deref()).await
+        }
+        ClientEventType::Hello { version } => {
+            (*version != PROTOCOL_VERSION).then(|| WsError::IncompatibleClient.into())
+        }
+        ClientEventType::Ack { id } => {
+            handlers::handle_ack(db, conn_id, id, event.id.as_deref()).await
+        }
+        ClientEventType::Message { content, to } => {
+            handlers::handle_message(
+                db,
+                &config.offline_queue,
+                user,
+                to,
+                content,
+                event.ecdsa_signature(),
+                event.id.as_deref(),
+            )
+            .await
+        }
+    };
+    server_event.map(|server_event| server_event.with_request_id(event.id))
 }
 
 /// Handle user disconnected
@@ -277,7 +558,8 @@ async fn user_disconnected(
     user: Option<UserModel>,
 ) {
     ONLINE_USERS.remove_user(conn_id).await;
-    if ONLINE_USERS.is_online(public_key).await.is_none() {
+    // Only update the last logout when the last connection of the user is closed
+    if !ONLINE_USERS.is_online(public_key).await {
         if let Some(mut user) = user.map(IntoActiveModel::into_active_model) {
             user.last_logout = Set(Utc::now());
             if let Err(err) = user.update(db_conn).await {
@@ -288,25 +570,16 @@ async fn user_disconnected(
     log::debug!("User disconnect: ConnId(={conn_id}) PublicKey(={public_key})");
 }
 
-pub fn route() -> Router {
-    let users_pinger = async {
-        /// Seconds to wait for pongs, before disconnecting the user
-        const WAIT_FOR_PONGS_SECS: u32 = 10;
-        /// Seconds to sleep between pings (10 minutes)
-        const SLEEP_SECS: u32 = 60 * 10;
-        loop {
-            log::debug!("Start pinging online users");
-            ONLINE_USERS.ping_all().await;
-            tokio_sleep(Duration::from_secs(u64::from(WAIT_FOR_PONGS_SECS))).await;
-            ONLINE_USERS.disconnect_inactive_users().await;
-            log::debug!("Done pinging online users and disconnected inactive ones");
-            tokio_sleep(Duration::from_secs(u64::from(SLEEP_SECS))).await;
-        }
-    };
-
-    tokio_spawn(users_pinger);
+/// The websocket route, the federation outbox worker is spawned with it
+pub fn route(
+    db_conn: Arc<DatabaseConnection>,
+    config: Arc<Config>,
+    federation: Arc<Federation>,
+) -> Router {
+    tokio_spawn(federation::outbox_worker(db_conn, config, federation));
 
     Router::new()
         .push(Router::with_path("chat").get(user_connected))
         .hoop(middlewares::signature_check)
+        .hoop(middlewares::ban_check)
 }
@@ -24,9 +24,9 @@
 use std::{net::IpAddr, path::PathBuf};
 
 use clap::Parser;
-use oxidetalis_core::types::Size;
+use oxidetalis_core::types::{PublicKey, Size};
 
-use crate::types::{Host, OpenApiViewer};
+use crate::types::{Host, OpenApiViewer, SlowClientPolicy};
 
 /// Header message, used in the help message
 const HEADER: &str = r#"Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
@@ -54,65 +54,143 @@ const FOOTER: &str = r#"Please report bugs to <https://git.4rs.nl/oxidetalis/oxi
 pub struct CliArgs {
     /// Path to the configuration file, toml format.
     #[clap(long, env = "OXIDETALIS_CONFIG")]
-    pub config:                  PathBuf,
+    pub config: PathBuf,
     /// Server name, for example, `example.com`.
     #[clap(long, env = "OXIDETALIS_SERVER_NAME")]
-    pub server_name:             Option<String>,
+    pub server_name: Option<String>,
     /// Local IP address to bind the server to.
     #[clap(long, env = "OXIDETALIS_SERVER_HOST")]
-    pub server_host:             Option<IpAddr>,
+    pub server_host: Option<IpAddr>,
     /// Port to bind the server to.
     #[clap(long, env = "OXIDETALIS_SERVER_PORT")]
-    pub server_port:             Option<u16>,
+    pub server_port: Option<u16>,
     /// Nonce cache size
     ///
     /// e.g. "50B", "300KB", "1MB", "1GB"
     #[clap(long, env = "OXIDETALIS_SERVER_NONCE_CACHE_SIZE")]
     pub server_nonce_cache_size: Option<Size>,
+    /// Cache size of the keys derived from the ECDH shared secrets
+    ///
+    /// e.g. "50B", "300KB", "1MB", "1GB"
+    #[clap(long, env = "OXIDETALIS_SERVER_SECRET_CACHE_SIZE")]
+    pub server_secret_cache_size: Option<Size>,
     /// Enable or disable user registration.
     #[clap(long, env = "OXIDETALIS_REGISTER_ENABLE")]
-    pub register_enable:         Option<bool>,
+    pub register_enable: Option<bool>,
     /// Hostname or IP address of the PostgreSQL database.
     #[clap(long, env = "OXIDETALIS_DB_HOST")]
-    pub postgres_host:           Option<Host>,
+    pub postgres_host: Option<Host>,
     /// Port number of the PostgreSQL database.
     #[clap(long, env = "OXIDETALIS_DB_PORT")]
-    pub postgres_port:           Option<u16>,
+    pub postgres_port: Option<u16>,
     /// Username for the PostgreSQL database.
     #[clap(long, env = "OXIDETALIS_DB_USER")]
-    pub postgres_user:           Option<String>,
+    pub postgres_user: Option<String>,
     /// Password for the PostgreSQL database.
     #[clap(long, env = "OXIDETALIS_DB_PASSWORD")]
-    pub postgres_password:       Option<String>,
+    pub postgres_password: Option<String>,
     /// Name of the PostgreSQL database.
     #[clap(long, env = "OXIDETALIS_DB_NAME")]
-    pub postgres_name:           Option<String>,
+    pub postgres_name: Option<String>,
     /// Enable or disable rate limiting.
     #[clap(long, env = "OXIDETALIS_RATELIMIT_ENABLE")]
-    pub ratelimit_enable:        Option<bool>,
+    pub ratelimit_enable: Option<bool>,
     /// Maximum number of requests allowed within a given time period for rate
     /// limiting.
     #[clap(long, env = "OXIDETALIS_RATELIMIT_LIMIT")]
-    pub ratelimit_limit:         Option<usize>,
+    pub ratelimit_limit: Option<usize>,
     /// Time period in seconds for rate limiting.
     #[clap(long, env = "OXIDETALIS_RATELIMIT_PREIOD")]
-    pub ratelimit_preiod:        Option<usize>,
+    pub ratelimit_preiod: Option<usize>,
     /// Enable or disable OpenAPI documentation generation.
     #[clap(long, env = "OXIDETALIS_OPENAPI_ENABLE")]
-    pub openapi_enable:          Option<bool>,
+    pub openapi_enable: Option<bool>,
     /// Title for the OpenAPI documentation.
     #[clap(long, env = "OXIDETALIS_OPENAPI_TITLE")]
-    pub openapi_title:           Option<String>,
+    pub openapi_title: Option<String>,
     /// Description for the OpenAPI documentation.
     #[clap(long, env = "OXIDETALIS_OPENAPI_DESCRIPTION")]
-    pub openapi_description:     Option<String>,
+    pub openapi_description: Option<String>,
     /// Path to serve the OpenAPI documentation.
     #[clap(long, env = "OXIDETALIS_OPENAPI_PATH")]
-    pub openapi_path:            Option<String>,
+    pub openapi_path: Option<String>,
     /// OpenAPI viewer to use for rendering the documentation.
     #[clap(long, env = "OXIDETALIS_OPENAPI_VIEWER")]
-    pub openapi_viewer:          Option<OpenApiViewer>,
+    pub openapi_viewer: Option<OpenApiViewer>,
     /// Path to the OpenAPI viewer HTML file.
     #[clap(long, env = "OXIDETALIS_OPENAPI_VIEWER_PATH")]
-    pub openapi_viewer_path:     Option<String>,
+    pub openapi_viewer_path: Option<String>,
+    /// Maximum size of the queued events per offline user
+    ///
+    /// e.g. "50B", "300KB", "1MB", "1GB"
+    #[clap(long, env = "OXIDETALIS_OFFLINE_QUEUE_MAX_SIZE")]
+    pub offline_queue_max_size: Option<Size>,
+    /// How long the queued events live in seconds, before they are dropped.
+    #[clap(long, env = "OXIDETALIS_OFFLINE_QUEUE_TTL_SECS")]
+    pub offline_queue_ttl_secs: Option<u64>,
+    /// Maximum number of events waiting to be sent to a single websocket
+    /// connection.
+    #[clap(long, env = "OXIDETALIS_WEBSOCKET_OUTBOUND_QUEUE_CAPACITY")]
+    pub websocket_outbound_queue_capacity: Option<usize>,
+    /// The policy that applied when a websocket connection can't keep up with
+    /// the server events.
+    #[clap(long, env = "OXIDETALIS_WEBSOCKET_SLOW_CLIENT_POLICY")]
+    pub websocket_slow_client_policy: Option<SlowClientPolicy>,
+    /// Seconds between the pings of a websocket connection.
+    #[clap(long, env = "OXIDETALIS_WEBSOCKET_PING_INTERVAL_SECS")]
+    pub websocket_ping_interval_secs: Option<u64>,
+    /// Seconds to wait for the pong, before disconnecting the websocket
+    /// connection.
+    #[clap(long, env = "OXIDETALIS_WEBSOCKET_PONG_TIMEOUT_SECS")]
+    pub websocket_pong_timeout_secs: Option<u64>,
+    /// Maximum size of a single websocket frame sent by the client.
+    ///
+    /// e.g. "50B", "300KB", "1MB", "1GB"
+    #[clap(long, env = "OXIDETALIS_WEBSOCKET_MAX_FRAME_SIZE")]
+    pub websocket_max_frame_size: Option<Size>,
+    /// How long the undelivered federation events are retried in seconds,
+    /// before giving up on them
+    #[clap(long, env = "OXIDETALIS_FEDERATION_OUTBOX_DEADLINE_SECS")]
+    pub federation_outbox_deadline_secs: Option<u64>,
+    /// The only homeservers that are allowed to federate with this homeserver,
+    /// separated by commas. All the homeservers are allowed if it's empty.
+    #[clap(
+        long,
+        env = "OXIDETALIS_FEDERATION_ALLOWED_SERVERS",
+        value_delimiter = ','
+    )]
+    pub federation_allowed_servers: Option<Vec<String>>,
+    /// The homeservers that are not allowed to federate with this homeserver,
+    /// separated by commas.
+    #[clap(
+        long,
+        env = "OXIDETALIS_FEDERATION_DENIED_SERVERS",
+        value_delimiter = ','
+    )]
+    pub federation_denied_servers: Option<Vec<String>>,
+    /// The public keys of the only homeservers that are allowed to federate
+    /// with this homeserver, separated by commas. All the homeservers are
+    /// allowed if it's empty.
+    #[clap(
+        long,
+        env = "OXIDETALIS_FEDERATION_ALLOWED_PUBLIC_KEYS",
+        value_delimiter = ','
+    )]
+    pub federation_allowed_public_keys: Option<Vec<PublicKey>>,
+    /// The public keys of the homeservers that are not allowed to federate
+    /// with this homeserver, separated by commas.
+    #[clap(
+        long,
+        env = "OXIDETALIS_FEDERATION_DENIED_PUBLIC_KEYS",
+        value_delimiter = ','
+    )]
+    pub federation_denied_public_keys: Option<Vec<PublicKey>>,
+    /// Allow the homeservers on the loopback, private and link-local
+    /// addresses.
+    #[clap(long, env = "OXIDETALIS_FEDERATION_ALLOW_PRIVATE_SERVERS")]
+    pub federation_allow_private_servers: Option<bool>,
+    /// Reach the loopback homeservers over plain HTTP, only for testing the
+    /// federation locally.
+    #[clap(long, env = "OXIDETALIS_FEDERATION_DEV_PLAIN_HTTP_LOOPBACK")]
+    pub federation_dev_plain_http_loopback: Option<bool>,
 }
@@ -42,6 +42,9 @@ pub(crate) mod server {
     pub const fn nonce_cache_size() -> Size {
         Size::MB(1)
     }
+    pub const fn secret_cache_size() -> Size {
+        Size::MB(1)
+    }
 }
 
 /// Ratelimit default configs
@@ -97,6 +100,50 @@ pub(crate) mod postgres {
     }
 }
 
+/// Offline queue default configs
+pub(crate) mod offline_queue {
+    use oxidetalis_core::types::Size;
+
+    pub const fn max_size() -> Size {
+        Size::MB(5)
+    }
+    pub const fn ttl_secs() -> u64 {
+        // 30 days
+        60 * 60 * 24 * 30
+    }
+}
+
+/// Websocket default configs
+pub(crate) mod websocket {
+    use oxidetalis_core::types::Size;
+
+    use crate::types::SlowClientPolicy;
+
+    pub const fn outbound_queue_capacity() -> usize {
+        256
+    }
+    pub const fn slow_client_policy() -> SlowClientPolicy {
+        SlowClientPolicy::Disconnect
+    }
+    pub const fn ping_interval_secs() -> u64 {
+        60
+    }
+    pub const fn pong_timeout_secs() -> u64 {
+        10
+    }
+    pub const fn max_frame_size() -> Size {
+        Size::MB(1)
+    }
+}
+
+/// Federation default configs
+pub(crate) mod federation {
+    pub const fn outbox_deadline_secs() -> u64 {
+        // 3 days
+        60 * 60 * 24 * 3
+    }
+}
+
 pub(crate) const fn bool_true() -> bool {
     true
 }
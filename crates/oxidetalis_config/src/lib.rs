@@ -23,7 +23,10 @@
 use std::{fs, io::Error as IoError, net::IpAddr, path::Path};
 
 use derivative::Derivative;
-use oxidetalis_core::{cipher::K256Secret, types::Size};
+use oxidetalis_core::{
+    cipher::K256Secret,
+    types::{PublicKey, Size},
+};
 use serde::{Deserialize, Serialize};
 use toml::{de::Error as TomlDeError, ser::Error as TomlSerError};
 
@@ -48,6 +51,8 @@ pub enum Error {
     SeToml(#[from] TomlSerError),
     #[error("Missing required option `--{0}`")]
     RequiredConfiguration(String),
+    #[error("The option `{0}` must be greater than zero")]
+    ZeroConfiguration(String),
 }
 
 /// Server startup configuration
@@ -57,19 +62,22 @@ pub enum Error {
 pub struct Server {
     /// Name of the server, for example, `example.com`
     #[derivative(Default(value = "defaults::server::name()"))]
-    pub server_name:      String,
+    pub server_name:       String,
     /// Host that the server will listen in
     #[derivative(Default(value = "defaults::server::host()"))]
-    pub host:             IpAddr,
+    pub host:              IpAddr,
     /// Port that the server will listen in
     #[derivative(Default(value = "defaults::server::port()"))]
-    pub port:             u16,
+    pub port:              u16,
     /// Server keypair
     #[derivative(Default(value = "defaults::server::private_key()"))]
-    pub private_key:      K256Secret,
+    pub private_key:       K256Secret,
     /// Nonce cache limit
     #[derivative(Default(value = "defaults::server::nonce_cache_size()"))]
-    pub nonce_cache_size: Size,
+    pub nonce_cache_size:  Size,
+    /// Cache limit of the keys derived from the ECDH shared secrets
+    #[derivative(Default(value = "defaults::server::secret_cache_size()"))]
+    pub secret_cache_size: Size,
 }
 
 /// Registration config
@@ -148,23 +156,100 @@ pub struct OpenApi {
     pub viewer_path: String,
 }
 
+/// Offline queue configuration, the queue of the events that are sent to
+/// offline users
+#[derive(Debug, Deserialize, Serialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct OfflineQueue {
+    /// Maximum size of the queued events per user
+    #[derivative(Default(value = "defaults::offline_queue::max_size()"))]
+    pub max_size: Size,
+    /// How long the queued events live in seconds, before they are dropped
+    #[derivative(Default(value = "defaults::offline_queue::ttl_secs()"))]
+    pub ttl_secs: u64,
+}
+
+/// Websocket configuration
+#[derive(Debug, Deserialize, Serialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct Websocket {
+    /// Maximum number of events waiting to be sent to a single connection
+    #[derivative(Default(value = "defaults::websocket::outbound_queue_capacity()"))]
+    pub outbound_queue_capacity: usize,
+    /// The policy that applied when a connection outbound queue is full
+    #[derivative(Default(value = "defaults::websocket::slow_client_policy()"))]
+    pub slow_client_policy:      SlowClientPolicy,
+    /// Seconds between the pings of a connection, each connection has its own
+    /// jittered timer
+    #[derivative(Default(value = "defaults::websocket::ping_interval_secs()"))]
+    pub ping_interval_secs:      u64,
+    /// Seconds to wait for the pong, before disconnecting the connection
+    #[derivative(Default(value = "defaults::websocket::pong_timeout_secs()"))]
+    pub pong_timeout_secs:       u64,
+    /// Maximum size of a single websocket frame sent by the client
+    #[derivative(Default(value = "defaults::websocket::max_frame_size()"))]
+    pub max_frame_size:          Size,
+}
+
+/// Federation configuration
+#[derive(Debug, Deserialize, Serialize, Derivative, Clone)]
+#[derivative(Default)]
+#[serde(default)]
+pub struct Federation {
+    /// How long the undelivered federation events are retried in seconds,
+    /// before giving up on them
+    #[derivative(Default(value = "defaults::federation::outbox_deadline_secs()"))]
+    pub outbox_deadline_secs:    u64,
+    /// The only homeservers that are allowed to federate with this homeserver,
+    /// all the homeservers are allowed if it's empty
+    pub allowed_servers:         Vec<String>,
+    /// The homeservers that are not allowed to federate with this homeserver
+    pub denied_servers:          Vec<String>,
+    /// The public keys of the only homeservers that are allowed to federate
+    /// with this homeserver, all the homeservers are allowed if it's empty
+    pub allowed_public_keys:     Vec<PublicKey>,
+    /// The public keys of the homeservers that are not allowed to federate
+    /// with this homeserver
+    pub denied_public_keys:      Vec<PublicKey>,
+    /// Whether to allow the homeservers on the loopback, private and
+    /// link-local addresses, they are rejected by default so the remote
+    /// homeservers can't make this homeserver reach the internal network
+    #[derivative(Default(value = "defaults::bool_false()"))]
+    pub allow_private_servers:   bool,
+    /// Whether to reach the loopback homeservers over plain HTTP, only for
+    /// testing the federation locally. It requires `allow_private_servers`
+    #[derivative(Default(value = "defaults::bool_false()"))]
+    pub dev_plain_http_loopback: bool,
+}
+
 #[derive(Deserialize, Serialize, Default, Clone)]
 /// Oxidetalis homeserver configurations
 pub struct Config {
     /// Server configuration (server startup configuration)
     #[serde(default)]
-    pub server:     Server,
+    pub server:        Server,
     /// Server registration configuration
     #[serde(default)]
-    pub register:   Register,
+    pub register:      Register,
     /// Database configuration
-    pub postgresdb: Postgres,
+    pub postgresdb:    Postgres,
     /// Ratelimit configuration
     #[serde(default)]
-    pub ratelimit:  Ratelimit,
+    pub ratelimit:     Ratelimit,
     /// OpenApi configuration
     #[serde(default)]
-    pub openapi:    OpenApi,
+    pub openapi:       OpenApi,
+    /// Offline queue configuration
+    #[serde(default)]
+    pub offline_queue: OfflineQueue,
+    /// Websocket configuration
+    #[serde(default)]
+    pub websocket:     Websocket,
+    /// Federation configuration
+    #[serde(default)]
+    pub federation:    Federation,
 }
 
 /// Check if required new configuration options are provided
@@ -176,6 +261,39 @@ fn check_required_new_config(args: &CliArgs) -> Result<(), Error> {
     Ok(())
 }
 
+/// Check that the options that can't be zero are not zero
+fn check_non_zero_config(config: &Config) -> Result<(), Error> {
+    let zero_option = [
+        (
+            "offline_queue.max_size",
+            config.offline_queue.max_size.as_bytes() == 0,
+        ),
+        ("offline_queue.ttl_secs", config.offline_queue.ttl_secs == 0),
+        (
+            "websocket.outbound_queue_capacity",
+            config.websocket.outbound_queue_capacity == 0,
+        ),
+        (
+            "websocket.ping_interval_secs",
+            config.websocket.ping_interval_secs == 0,
+        ),
+        (
+            "websocket.pong_timeout_secs",
+            config.websocket.pong_timeout_secs == 0,
+        ),
+        (
+            "websocket.max_frame_size",
+            config.websocket.max_frame_size.as_bytes() == 0,
+        ),
+    ]
+    .into_iter()
+    .find_map(|(option, is_zero)| is_zero.then_some(option));
+    if let Some(option) = zero_option {
+        return Err(Error::ZeroConfiguration(option.to_owned()));
+    }
+    Ok(())
+}
+
 impl Config {
     /// Load the config from toml file and command-line options
     ///
@@ -188,6 +306,7 @@ impl Config {
     /// ## Errors
     /// - Failed to read the config file
     /// - Invalid toml file
+    /// - An option that can't be zero is zero
     pub fn load(args: CliArgs) -> Result<Self, Error> {
         let mut config = if args.config.exists() {
             log::info!("Loading configuration from {}", args.config.display());
@@ -210,6 +329,10 @@ impl Config {
             &mut config.server.nonce_cache_size,
             args.server_nonce_cache_size,
         );
+        assign_option(
+            &mut config.server.secret_cache_size,
+            args.server_secret_cache_size,
+        );
         assign_option(&mut config.register.enable, args.register_enable);
         assign_option(&mut config.postgresdb.host, args.postgres_host);
         assign_option(&mut config.postgresdb.port, args.postgres_port);
@@ -225,7 +348,64 @@ impl Config {
         assign_option(&mut config.openapi.path, args.openapi_path);
         assign_option(&mut config.openapi.viewer, args.openapi_viewer);
         assign_option(&mut config.openapi.viewer_path, args.openapi_viewer_path);
+        assign_option(
+            &mut config.offline_queue.max_size,
+            args.offline_queue_max_size,
+        );
+        assign_option(
+            &mut config.offline_queue.ttl_secs,
+            args.offline_queue_ttl_secs,
+        );
+        assign_option(
+            &mut config.websocket.outbound_queue_capacity,
+            args.websocket_outbound_queue_capacity,
+        );
+        assign_option(
+            &mut config.websocket.slow_client_policy,
+            args.websocket_slow_client_policy,
+        );
+        assign_option(
+            &mut config.websocket.ping_interval_secs,
+            args.websocket_ping_interval_secs,
+        );
+        assign_option(
+            &mut config.websocket.pong_timeout_secs,
+            args.websocket_pong_timeout_secs,
+        );
+        assign_option(
+            &mut config.websocket.max_frame_size,
+            args.websocket_max_frame_size,
+        );
+        assign_option(
+            &mut config.federation.outbox_deadline_secs,
+            args.federation_outbox_deadline_secs,
+        );
+        assign_option(
+            &mut config.federation.allowed_servers,
+            args.federation_allowed_servers,
+        );
+        assign_option(
+            &mut config.federation.denied_servers,
+            args.federation_denied_servers,
+        );
+        assign_option(
+            &mut config.federation.allowed_public_keys,
+            args.federation_allowed_public_keys,
+        );
+        assign_option(
+            &mut config.federation.denied_public_keys,
+            args.federation_denied_public_keys,
+        );
+        assign_option(
+            &mut config.federation.allow_private_servers,
+            args.federation_allow_private_servers,
+        );
+        assign_option(
+            &mut config.federation.dev_plain_http_loopback,
+            args.federation_dev_plain_http_loopback,
+        );
 
+        check_non_zero_config(&config)?;
         config.write(&args.config)?;
         Ok(config)
     }
@@ -41,6 +41,21 @@ pub enum OpenApiViewer {
     SwaggerUi,
 }
 
+/// The policy that applied when a websocket client can't keep up with the
+/// server events (its outbound queue is full)
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "PascalCase")]
+pub enum SlowClientPolicy {
+    /// Disconnect the client. The undelivered stored events (messages, chat
+    /// requests and responses) will be sent in the next connection, the other
+    /// events are dropped
+    Disconnect,
+    /// Keep the client connected. The undelivered stored events stay in the
+    /// offline queue and will be sent in the next connection, the other events
+    /// are dropped
+    Spill,
+}
+
 /// Host type, a wrapper around `url::Host`
 ///
 /// Because `url::Host` does not implement `FromStr`, we need to wrap it
@@ -68,6 +83,15 @@ impl fmt::Display for Host {
     }
 }
 
+impl fmt::Display for SlowClientPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Disconnect => write!(f, "Disconnect"),
+            Self::Spill => write!(f, "Spill"),
+        }
+    }
+}
+
 impl OpenApiViewer {
     /// Create a router for the viewer
     pub fn into_router(&self, config: &crate::Config) -> salvo_core::Router {
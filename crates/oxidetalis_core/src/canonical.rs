@@ -0,0 +1,231 @@
+// OxideTalis Messaging Protocol homeserver core implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! The canonical form of the signed HTTP requests.
+//!
+//! The canonical request binds the method, the full path, the sorted query
+//! parameters, the `X-OTMP-*` headers and the SHA-256 hash of the body, the
+//! signature of the request is the signature of its canonical form.
+//!
+//! ```text
+//! METHOD\n
+//! /path\n
+//! sorted=query&parameters\n
+//! x-otmp-header:value\n   (one line per header, sorted)
+//! hex(sha256(body))
+//! ```
+
+use std::fmt;
+
+use sha2::{Digest, Sha256};
+use url::form_urlencoded;
+
+use crate::SIGNATURE_HEADER;
+
+/// The prefix of the headers that are covered by the signature, the signature
+/// header itself is not covered.
+pub const SIGNED_HEADERS_PREFIX: &str = "x-otmp-";
+
+/// The canonical form of a signed HTTP request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanonicalRequest {
+    /// The uppercased request method
+    method:    String,
+    /// The request path
+    path:      String,
+    /// The decoded query parameters
+    query:     Vec<(String, String)>,
+    /// The lowercased signed headers names and their values
+    headers:   Vec<(String, String)>,
+    /// SHA-256 hash of the request body
+    body_hash: [u8; 32],
+}
+
+impl CanonicalRequest {
+    /// Create a canonical request without query parameters and headers
+    pub fn new(method: &str, path: &str, body: &[u8]) -> Self {
+        Self {
+            method:    method.to_ascii_uppercase(),
+            path:      path.to_owned(),
+            query:     Vec::new(),
+            headers:   Vec::new(),
+            body_hash: Sha256::digest(body).into(),
+        }
+    }
+
+    /// Add the query parameters of the raw query string, without the leading
+    /// `?`. The parameters are percent-decoded, so the encoding of the query
+    /// doesn't change the canonical form.
+    #[must_use]
+    pub fn with_query(mut self, query: &str) -> Self {
+        self.query.extend(
+            form_urlencoded::parse(query.as_bytes())
+                .map(|(name, value)| (name.into_owned(), value.into_owned())),
+        );
+        self
+    }
+
+    /// Add a header to the canonical request. The header is ignored if it's
+    /// not a signed header, see [`is_signed_header`].
+    #[must_use]
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        if is_signed_header(name) {
+            self.headers
+                .push((name.to_ascii_lowercase(), value.trim().to_owned()));
+        }
+        self
+    }
+
+    /// Returns the canonical request as bytes, the data to sign
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.to_string().into_bytes()
+    }
+}
+
+impl fmt::Display for CanonicalRequest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut query = self.query.clone();
+        query.sort_unstable();
+        let mut headers = self.headers.clone();
+        headers.sort_unstable();
+
+        writeln!(f, "{}", self.method)?;
+        writeln!(f, "{}", self.path)?;
+        writeln!(
+            f,
+            "{}",
+            form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(query)
+                .finish()
+        )?;
+        for (name, value) in headers {
+            writeln!(f, "{name}:{value}")?;
+        }
+        write!(f, "{}", hex::encode(self.body_hash))
+    }
+}
+
+/// Returns true if the header is covered by the request signature, the
+/// `X-OTMP-*` headers except the signature header.
+pub fn is_signed_header(name: &str) -> bool {
+    name.len() > SIGNED_HEADERS_PREFIX.len()
+        && name.is_char_boundary(SIGNED_HEADERS_PREFIX.len())
+        && name[..SIGNED_HEADERS_PREFIX.len()].eq_ignore_ascii_case(SIGNED_HEADERS_PREFIX)
+        && !name.eq_ignore_ascii_case(SIGNATURE_HEADER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_signed_header, CanonicalRequest};
+
+    #[test]
+    fn canonical_form() {
+        let request = CanonicalRequest::new("post", "/federation/events", br#"{"a":1}"#)
+            .with_query("b=2&a=1")
+            .with_header("X-OTMP-SERVER", " example.com ")
+            .with_header("X-OTMP-PUBLIC", "key")
+            .with_header("Content-Type", "application/json")
+            .with_header("X-OTMP-SIGNATURE", "signature");
+
+        assert_eq!(
+            request.to_string(),
+            concat!(
+                "POST\n",
+                "/federation/events\n",
+                "a=1&b=2\n",
+                "x-otmp-public:key\n",
+                "x-otmp-server:example.com\n",
+                "015abd7f5cc57a2dd94b7590f04ad8084273905ee33ec5cebeae62276a97f862",
+            ),
+            "Wrong canonical form"
+        );
+        assert_eq!(
+            request.to_bytes(),
+            request.to_string().into_bytes(),
+            "The bytes must be the canonical form"
+        );
+    }
+
+    #[test]
+    fn empty_request() {
+        assert_eq!(
+            CanonicalRequest::new("GET", "/", b"").to_string(),
+            "GET\n/\n\ne3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "Wrong canonical form of the request without query and headers"
+        );
+    }
+
+    #[test]
+    fn query_encoding() {
+        assert_eq!(
+            CanonicalRequest::new("GET", "/users", b"")
+                .with_query("name=a%20b&page=1")
+                .to_string(),
+            CanonicalRequest::new("GET", "/users", b"")
+                .with_query("page=1&name=a+b")
+                .to_string(),
+            "The query encoding and order must not change the canonical form"
+        );
+    }
+
+    #[test]
+    fn header_order_and_case() {
+        assert_eq!(
+            CanonicalRequest::new("GET", "/", b"")
+                .with_header("X-OTMP-A", "1")
+                .with_header("x-otmp-b", "2")
+                .to_string(),
+            CanonicalRequest::new("GET", "/", b"")
+                .with_header("x-otmp-b", "2")
+                .with_header("X-Otmp-A", "1")
+                .to_string(),
+            "The headers order and names case must not change the canonical form"
+        );
+    }
+
+    #[test]
+    fn body_is_covered() {
+        assert_ne!(
+            CanonicalRequest::new("POST", "/", b"a"),
+            CanonicalRequest::new("POST", "/", b"b"),
+            "The body must change the canonical form"
+        );
+    }
+
+    #[test]
+    fn signed_headers() {
+        assert!(is_signed_header("X-OTMP-PUBLIC"), "Must be signed");
+        assert!(is_signed_header("x-otmp-server"), "Must be signed");
+        assert!(
+            !is_signed_header("X-OTMP-SIGNATURE"),
+            "The signature header can't sign itself"
+        );
+        assert!(
+            !is_signed_header("X-OTMP-"),
+            "The prefix alone is not a header"
+        );
+        assert!(!is_signed_header("Content-Type"), "Must not be signed");
+        assert!(
+            !is_signed_header("X-OTMé-A"),
+            "The non-ASCII header must not panic"
+        );
+    }
+}
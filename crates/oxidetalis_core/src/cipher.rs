@@ -24,10 +24,11 @@
 
 use std::time::{SystemTime, UNIX_EPOCH};
 
-use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
 use hmac::Mac;
 use k256::{
     ecdh::diffie_hellman,
+    ecdsa::{signature::Signer, Signature as EcdsaSignature, SigningKey as EcdsaSigningKey},
     elliptic_curve::sec1::ToEncodedPoint,
     FieldBytes,
     NonZeroScalar,
@@ -35,10 +36,15 @@ use k256::{
 };
 use rand::{thread_rng, RngCore};
 
-use crate::types::{
-    PrivateKey as CorePrivateKey,
-    PublicKey as CorePublicKey,
-    Signature as CoreSignature,
+use crate::{
+    envelope::{self, AeadAlgorithm, OpenedEnvelope},
+    types::{
+        ecdsa_signed_data,
+        PrivateKey as CorePrivateKey,
+        PublicKey as CorePublicKey,
+        Signature as CoreSignature,
+        SignatureVersion,
+    },
 };
 
 /// The errors that can occur during in the cipher module.
@@ -57,6 +63,9 @@ pub enum CipherError {
     /// A decryption error
     #[error("Decryption Error")]
     Decryption,
+    /// The encryption envelope is malformed, or its algorithm is unknown
+    #[error("Invalid encryption envelope")]
+    InvalidEnvelope,
     /// Invalid base58 string
     #[error("Invalid base58 string `{0}`")]
     InvalidBase58(String),
@@ -66,10 +75,33 @@ pub enum CipherError {
 }
 #[allow(clippy::absolute_paths)]
 type Result<T> = std::result::Result<T, CipherError>;
-type Aes256CbcEnc = cbc::Encryptor<aes::Aes256>;
 type Aes256CbcDec = cbc::Decryptor<aes::Aes256>;
 type HmacSha256 = hmac::Hmac<sha2::Sha256>;
 
+/// The purpose of a key derived from the ECDH shared secret, each purpose has
+/// its own key, so a key of a purpose can't be used for another.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum KeyPurpose {
+    /// Authentication between a client and a homeserver, or between two
+    /// homeservers. Used to sign the requests and the events
+    ClientServerAuth,
+    /// Encryption of the data between two users
+    UserEncryption,
+    /// Authentication of the data between two users
+    UserMac,
+}
+
+impl KeyPurpose {
+    /// Returns the HKDF info string of the purpose
+    pub const fn label(&self) -> &'static [u8] {
+        match self {
+            Self::ClientServerAuth => b"OTMP v2 client-server auth",
+            Self::UserEncryption => b"OTMP v2 user-to-user encryption",
+            Self::UserMac => b"OTMP v2 user-to-user mac",
+        }
+    }
+}
+
 /// An wrapper around the k256 crate to provide a simple API for ecdh key
 /// exchange and keypair generation.
 #[derive(Clone)]
@@ -110,15 +142,7 @@ impl K256Secret {
     ///
     /// The signature is exiplained in the OTMP specification.
     pub fn sign_with_shared_secret(data: &[u8], shared_secret: &[u8; 32]) -> CoreSignature {
-        let mut time_and_nonce = [0u8; 24];
-        time_and_nonce[0..=7].copy_from_slice(
-            &SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("SystemTime before UNIX EPOCH!")
-                .as_secs()
-                .to_be_bytes(),
-        );
-        thread_rng().fill_bytes(&mut time_and_nonce[8..=23]);
+        let time_and_nonce = time_and_nonce();
 
         let mut hmac_secret = [0u8; 56];
         hmac_secret[0..=31].copy_from_slice(shared_secret);
@@ -130,6 +154,21 @@ impl K256Secret {
         CoreSignature::from(signature)
     }
 
+    /// Sign a data with the private key, using ECDSA.
+    ///
+    /// Unlike the shared secret signature, only the owner of the private key
+    /// can produce it, so anyone with the public key can verify the authorship
+    /// of the data.
+    pub fn sign_ecdsa(&self, data: &[u8]) -> CoreSignature {
+        let time_and_nonce = time_and_nonce();
+        let timestamp = time_and_nonce[0..=7].try_into().expect("Is 8 bytes");
+        let nonce = time_and_nonce[8..=23].try_into().expect("Is 16 bytes");
+        let signature: EcdsaSignature =
+            EcdsaSigningKey::from(self.scalar).sign(&ecdsa_signed_data(data, &timestamp, &nonce));
+
+        CoreSignature::ecdsa(signature.to_bytes().into(), timestamp, nonce)
+    }
+
     /// Returns the public key.
     pub fn pubkey(&self) -> CorePublicKey {
         CorePublicKey::try_from(
@@ -146,7 +185,25 @@ impl K256Secret {
     }
 
     /// Compute the shared secret with the given public key.
+    ///
+    /// This is the legacy key, derived without a label, it's only used to
+    /// decrypt the legacy encrypted data. Use [`K256Secret::derive_key`] to
+    /// get a key for a specific purpose.
     pub fn shared_secret(&self, with: &CorePublicKey) -> [u8; 32] {
+        self.hkdf_expand(with, &[])
+    }
+
+    /// Derive a key for the given purpose from the ECDH shared secret with the
+    /// given public key.
+    ///
+    /// The key is derived with HKDF-SHA256 without salt, using the purpose
+    /// label as the info string, see [`KeyPurpose::label`].
+    pub fn derive_key(&self, with: &CorePublicKey, purpose: KeyPurpose) -> [u8; 32] {
+        self.hkdf_expand(with, purpose.label())
+    }
+
+    /// Expand the ECDH shared secret with the given public key using HKDF
+    fn hkdf_expand(&self, with: &CorePublicKey, info: &[u8]) -> [u8; 32] {
         let mut secret_buf = [0u8; 32];
         diffie_hellman(
             self.scalar,
@@ -155,7 +212,7 @@ impl K256Secret {
                 .as_affine(),
         )
         .extract::<sha2::Sha256>(None)
-        .expand(&[], &mut secret_buf)
+        .expand(info, &mut secret_buf)
         .expect("The buffer size is correct");
 
         secret_buf
@@ -163,29 +220,82 @@ impl K256Secret {
 
     /// Encrypt a data with the shared secret.
     ///
-    /// The data is encrypted using AES-256-CBC with a random IV (last 16 bytes
-    /// of the ciphertext).
+    /// The data is encrypted in an authenticated encryption envelope, using
+    /// the default AEAD algorithm and without associated data, see
+    /// [`K256Secret::encrypt_envelope`].
     pub fn encrypt_data(&self, encrypt_to: &CorePublicKey, data: &[u8]) -> Vec<u8> {
-        let mut iv = [0u8; 16];
-        thread_rng().fill_bytes(&mut iv);
+        self.encrypt_envelope(encrypt_to, data, &[], AeadAlgorithm::default())
+    }
 
-        let mut ciphertext =
-            Aes256CbcEnc::new(self.shared_secret(encrypt_to).as_slice().into(), &iv.into())
-                .encrypt_padded_vec_mut::<Pkcs7>(data);
-        ciphertext.extend(&iv);
-        ciphertext
+    /// Encrypt a data with the user-to-user encryption key in an authenticated
+    /// encryption envelope, the associated data is authenticated but not
+    /// encrypted.
+    ///
+    /// The envelope format is explained in the [`envelope`] module.
+    pub fn encrypt_envelope(
+        &self,
+        encrypt_to: &CorePublicKey,
+        data: &[u8],
+        associated_data: &[u8],
+        algorithm: AeadAlgorithm,
+    ) -> Vec<u8> {
+        envelope::seal(
+            &self.derive_key(encrypt_to, KeyPurpose::UserEncryption),
+            algorithm,
+            associated_data,
+            data,
+        )
+    }
+
+    /// Decrypt an authenticated encryption envelope with the user-to-user
+    /// encryption key, returns the data with its associated data.
+    ///
+    /// ## Errors
+    /// - If the envelope is malformed or its algorithm is unknown
+    /// - Falid to decrypt the data (invalid or tampered envelope)
+    pub fn decrypt_envelope(
+        &self,
+        decrypt_from: &CorePublicKey,
+        data: &[u8],
+    ) -> Result<OpenedEnvelope> {
+        envelope::open(
+            &self.derive_key(decrypt_from, KeyPurpose::UserEncryption),
+            data,
+        )
     }
 
     /// Decrypt a data with the shared secret.
     ///
-    /// The data is decrypted using AES-256-CBC with the IV being the last 16
-    /// bytes of the ciphertext.
+    /// The data is either an authenticated encryption envelope, see
+    /// [`K256Secret::decrypt_envelope`], or in the legacy format, see
+    /// [`K256Secret::decrypt_legacy`]. The legacy format is only tried if the
+    /// data is not an envelope, so a tampered envelope is never decrypted
+    /// without authentication.
+    ///
+    /// ## Errors
+    /// - If the envelope algorithm is unknown
+    /// - Falid to decrypt the data (invalid or tampered data)
+    pub fn decrypt_data(&self, decrypt_from: &CorePublicKey, data: &[u8]) -> Result<Vec<u8>> {
+        if envelope::is_envelope(data) {
+            return self
+                .decrypt_envelope(decrypt_from, data)
+                .map(|opened| opened.data);
+        }
+        self.decrypt_legacy(decrypt_from, data)
+    }
+
+    /// Decrypt a data in the legacy format with the shared secret.
+    ///
+    /// The data is encrypted using AES-256-CBC with the IV being the last 16
+    /// bytes of the ciphertext. The legacy format is not authenticated, so a
+    /// tampered data may be decrypted to a garbage, it's only supported to
+    /// decrypt the old data.
     ///
     /// ## Errors
     /// - If the data less then 16 bytes.
     /// - If the iv less then 16 bytes.
     /// - Falid to decrypt the data (invalid encrypted data)
-    pub fn decrypt_data(&self, decrypt_from: &CorePublicKey, data: &[u8]) -> Result<Vec<u8>> {
+    pub fn decrypt_legacy(&self, decrypt_from: &CorePublicKey, data: &[u8]) -> Result<Vec<u8>> {
         let (ciphertext, iv) =
             data.split_at(data.len().checked_sub(16).ok_or(CipherError::Decryption)?);
 
@@ -201,25 +311,317 @@ impl K256Secret {
         .map_err(|_| CipherError::Decryption)
     }
 
-    /// Sign a data with the shared secret.
+    /// Sign a data with the client-server authentication key.
     ///
     /// The signature is explained in the OTMP specification.
     pub fn sign(&self, data: &[u8], sign_to: &CorePublicKey) -> CoreSignature {
-        Self::sign_with_shared_secret(data, &self.shared_secret(sign_to))
+        Self::sign_with_shared_secret(
+            data,
+            &self.derive_key(sign_to, KeyPurpose::ClientServerAuth),
+        )
     }
 
-    /// Verify the given signature with the signer.
+    /// Verify the given signature with the signer, the HMAC signature is
+    /// verified with the client-server authentication key and the ECDSA one
+    /// with the signer public key.
     ///
     /// Note:
     /// The time and the nonce will not be checked here
     pub fn verify(&self, data: &[u8], signature: &CoreSignature, signer: &CorePublicKey) -> bool {
-        signature.verify(data, &self.shared_secret(signer))
+        match signature.version() {
+            SignatureVersion::Hmac => {
+                signature.verify(data, &self.derive_key(signer, KeyPurpose::ClientServerAuth))
+            }
+            SignatureVersion::Ecdsa => signature.verify_ecdsa(data, signer),
+        }
     }
 }
 
+/// Returns the current timestamp and a random nonce, `timestamp || nonce`
+fn time_and_nonce() -> [u8; 24] {
+    let mut time_and_nonce = [0u8; 24];
+    time_and_nonce[0..=7].copy_from_slice(
+        &SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("SystemTime before UNIX EPOCH!")
+            .as_secs()
+            .to_be_bytes(),
+    );
+    thread_rng().fill_bytes(&mut time_and_nonce[8..=23]);
+    time_and_nonce
+}
+
 /// Compute the HMAC-SHA256 of the given data with the given secret.
 pub(crate) fn hmac_sha256(data: &[u8], secret: &[u8]) -> [u8; 32] {
     let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC can take key of any size");
     mac.update(data);
     mac.finalize().into_bytes().into()
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use aes::cipher::{block_padding::Pkcs7, BlockEncryptMut, KeyIvInit};
+
+    use super::{CipherError, K256Secret, KeyPurpose};
+    use crate::types::{PrivateKey, Signature, SignatureVersion};
+
+    /// Returns the keypair of the private key filled with the byte
+    fn keypair(byte: u8) -> K256Secret {
+        K256Secret::from_privkey(&PrivateKey::try_from([byte; 32]).expect("Is valid private key"))
+    }
+
+    #[test]
+    fn ecdsa_sign_verify() {
+        let signer = K256Secret::new();
+        let verifier = K256Secret::new();
+        let signature = signer.sign_ecdsa(b"data");
+
+        assert_eq!(
+            signature.version(),
+            SignatureVersion::Ecdsa,
+            "Wrong signature version"
+        );
+        assert!(
+            signature.verify_ecdsa(b"data", &signer.pubkey()),
+            "The signature must be valid"
+        );
+        assert!(
+            verifier.verify(b"data", &signature, &signer.pubkey()),
+            "The signature must be valid for any verifier"
+        );
+        assert!(
+            !signature.verify_ecdsa(b"other data", &signer.pubkey()),
+            "The signature of another data must be invalid"
+        );
+        assert!(
+            !signature.verify_ecdsa(b"data", &verifier.pubkey()),
+            "The signature of another signer must be invalid"
+        );
+    }
+
+    #[test]
+    fn ecdsa_covers_timestamp_and_nonce() {
+        let signer = K256Secret::new();
+        let mut bytes = signer
+            .sign_ecdsa(b"data")
+            .ecdsa_bytes()
+            .expect("Is ECDSA signature");
+        // The last byte of the nonce
+        bytes[88] ^= 1;
+        let signature = Signature::try_from(bytes.as_slice()).expect("Is valid encoding");
+
+        assert!(
+            !signature.verify_ecdsa(b"data", &signer.pubkey()),
+            "The signature with a changed nonce must be invalid"
+        );
+    }
+
+    #[test]
+    fn ecdsa_encoding() {
+        let signature = K256Secret::new().sign_ecdsa(b"data");
+        let bytes = signature.ecdsa_bytes().expect("Is ECDSA signature");
+
+        assert_eq!(bytes[0], 1, "Wrong version byte");
+        assert_eq!(
+            signature.to_bytes(),
+            bytes.to_vec(),
+            "The ECDSA signature must be encoded with its version"
+        );
+        assert_eq!(
+            Signature::try_from(bytes.as_slice()).expect("Is valid encoding"),
+            signature,
+            "The signature must be decoded as it's encoded"
+        );
+        assert_eq!(
+            Signature::from_str(&signature.to_string()).expect("Is valid hex"),
+            signature,
+            "The signature must be parsed as it's displayed"
+        );
+        assert_eq!(
+            signature.hmac_output(),
+            &[0; 32],
+            "The ECDSA signature has no HMAC output"
+        );
+    }
+
+    #[test]
+    fn hmac_is_not_ecdsa() {
+        let signer = K256Secret::new();
+        let verifier = K256Secret::new();
+        let signature = signer.sign(b"data", &verifier.pubkey());
+
+        assert_eq!(
+            signature.version(),
+            SignatureVersion::Hmac,
+            "Wrong signature version"
+        );
+        assert!(
+            verifier.verify(b"data", &signature, &signer.pubkey()),
+            "The signature must be valid"
+        );
+        assert!(
+            !signature.verify_ecdsa(b"data", &signer.pubkey()),
+            "The HMAC signature is not an ECDSA signature"
+        );
+        assert_eq!(signature.ecdsa_bytes(), None, "It's not an ECDSA signature");
+        assert_eq!(
+            signature.to_bytes(),
+            signature.as_bytes().to_vec(),
+            "The HMAC signature must be encoded without a version"
+        );
+    }
+
+    #[test]
+    fn invalid_signature_length() {
+        assert!(
+            Signature::try_from([0u8; 55].as_slice()).is_err(),
+            "The short signature must be rejected"
+        );
+        assert!(
+            Signature::try_from([0u8; 89].as_slice()).is_err(),
+            "The ECDSA signature with an unknown version must be rejected"
+        );
+    }
+
+    #[test]
+    fn encrypt_decrypt_data() {
+        let sender = K256Secret::new();
+        let recipient = K256Secret::new();
+        let encrypted = sender.encrypt_data(&recipient.pubkey(), b"data");
+
+        assert_eq!(
+            recipient
+                .decrypt_data(&sender.pubkey(), &encrypted)
+                .expect("Is valid envelope"),
+            b"data",
+            "Wrong decrypted data"
+        );
+        assert!(
+            K256Secret::new()
+                .decrypt_data(&sender.pubkey(), &encrypted)
+                .is_err(),
+            "Only the recipient can decrypt the data"
+        );
+    }
+
+    #[test]
+    fn legacy_data() {
+        let sender = K256Secret::new();
+        let recipient = K256Secret::new();
+        let iv = [3u8; 16];
+        let mut legacy = cbc::Encryptor::<aes::Aes256>::new(
+            sender.shared_secret(&recipient.pubkey()).as_slice().into(),
+            iv.as_slice().into(),
+        )
+        .encrypt_padded_vec_mut::<Pkcs7>(b"legacy data");
+        legacy.extend_from_slice(&iv);
+
+        assert_eq!(
+            recipient
+                .decrypt_data(&sender.pubkey(), &legacy)
+                .expect("Is valid legacy data"),
+            b"legacy data",
+            "The legacy data must be decrypted for migration"
+        );
+        assert_eq!(
+            recipient
+                .decrypt_legacy(&sender.pubkey(), &legacy)
+                .expect("Is valid legacy data"),
+            b"legacy data",
+            "Wrong decrypted legacy data"
+        );
+        assert!(
+            recipient.decrypt_legacy(&sender.pubkey(), &[0; 8]).is_err(),
+            "The data shorter than the IV must be rejected"
+        );
+    }
+
+    #[test]
+    fn tampered_envelope_is_not_legacy() {
+        let sender = K256Secret::new();
+        let recipient = K256Secret::new();
+        let mut encrypted = sender.encrypt_data(&recipient.pubkey(), b"data");
+        let last = encrypted.len() - 1;
+        encrypted[last] ^= 1;
+
+        assert!(
+            matches!(
+                recipient.decrypt_data(&sender.pubkey(), &encrypted),
+                Err(CipherError::Decryption)
+            ),
+            "The tampered envelope must not fall back to the legacy format"
+        );
+    }
+
+    #[test]
+    fn keypair_vector() {
+        assert_eq!(
+            hex::encode(keypair(0x11).pubkey().as_bytes()),
+            "034f355bdcb7cc0af728ef3cceb9615d90684bb5b2ca5f859ab0f0b704075871aa",
+            "Wrong public key"
+        );
+        assert_eq!(
+            keypair(0x22).pubkey().to_string(),
+            "gCZ84X4PtiYVEQVZv8yvg3wCkyW4KrKxEAgokWUNvTwC",
+            "Wrong public key"
+        );
+    }
+
+    #[test]
+    fn derive_key_vectors() {
+        let alice = keypair(0x11);
+        let bob = keypair(0x22);
+
+        for (purpose, expected) in [
+            (
+                KeyPurpose::ClientServerAuth,
+                "eccb980d163481e3db8d56df01ca0ab5fd23815bb44a514d0a8abfef91aabdf6",
+            ),
+            (
+                KeyPurpose::UserEncryption,
+                "fd27c0b01558474617961ca02795224acbbb2db0e5e19021b3b65460bf34ec1f",
+            ),
+            (
+                KeyPurpose::UserMac,
+                "7191ceeca3ba6b2147a2480ad0b9915cd51f7160552e608c017d29f765f0fe2f",
+            ),
+        ] {
+            assert_eq!(
+                hex::encode(alice.derive_key(&bob.pubkey(), purpose)),
+                expected,
+                "Wrong {purpose:?} key"
+            );
+            assert_eq!(
+                alice.derive_key(&bob.pubkey(), purpose),
+                bob.derive_key(&alice.pubkey(), purpose),
+                "Both sides must derive the same {purpose:?} key"
+            );
+        }
+        assert_eq!(
+            hex::encode(alice.shared_secret(&bob.pubkey())),
+            "7ccdcb06abbfab49982e3767d05f50388c3a4ca45f059cb6634d048a4ac91791",
+            "Wrong legacy shared secret"
+        );
+    }
+
+    #[test]
+    fn purpose_labels() {
+        assert_eq!(
+            KeyPurpose::ClientServerAuth.label(),
+            b"OTMP v2 client-server auth",
+            "Wrong label"
+        );
+        assert_eq!(
+            KeyPurpose::UserEncryption.label(),
+            b"OTMP v2 user-to-user encryption",
+            "Wrong label"
+        );
+        assert_eq!(
+            KeyPurpose::UserMac.label(),
+            b"OTMP v2 user-to-user mac",
+            "Wrong label"
+        );
+    }
+}
@@ -0,0 +1,321 @@
+// OxideTalis Messaging Protocol homeserver core implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Authenticated encryption envelope.
+//!
+//! The envelope is a versioned binary format of AEAD encrypted data, the
+//! header is authenticated with the data, so the algorithm, the nonce and the
+//! associated data can't be changed without failing the decryption.
+//!
+//! ```text
+//! magic "OTMP" (4) || version (1) || algorithm id (1) || nonce (12)
+//!     || associated data length, big endian (4) || associated data
+//!     || ciphertext with the authentication tag (16)
+//! ```
+
+use aes_gcm::Aes256Gcm;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit, Payload},
+    ChaCha20Poly1305,
+};
+use rand::{thread_rng, RngCore};
+
+use crate::cipher::CipherError;
+
+/// The magic bytes of the envelope
+const MAGIC: &[u8; 4] = b"OTMP";
+/// The current version of the envelope
+const VERSION: u8 = 1;
+/// Length of the AEAD nonce
+const NONCE_LENGTH: usize = 12;
+/// Length of the AEAD authentication tag
+const TAG_LENGTH: usize = 16;
+/// Length of the envelope header, without the associated data
+const HEADER_LENGTH: usize = MAGIC.len() + 1 + 1 + NONCE_LENGTH + 4;
+
+#[allow(clippy::absolute_paths)]
+type Result<T> = std::result::Result<T, CipherError>;
+
+/// The AEAD algorithm of the envelope
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, Hash)]
+pub enum AeadAlgorithm {
+    /// ChaCha20-Poly1305, the default algorithm
+    #[default]
+    ChaCha20Poly1305,
+    /// AES-256-GCM
+    Aes256Gcm,
+}
+
+/// Decrypted envelope
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OpenedEnvelope {
+    /// The algorithm that the data was encrypted with
+    pub algorithm:       AeadAlgorithm,
+    /// The associated data, authenticated but not encrypted
+    pub associated_data: Vec<u8>,
+    /// The decrypted data
+    pub data:            Vec<u8>,
+}
+
+impl AeadAlgorithm {
+    /// Returns the algorithm id in the envelope
+    pub const fn id(&self) -> u8 {
+        match self {
+            Self::ChaCha20Poly1305 => 1,
+            Self::Aes256Gcm => 2,
+        }
+    }
+
+    /// Returns the algorithm of the id, if it's known
+    pub const fn from_id(id: u8) -> Option<Self> {
+        match id {
+            1 => Some(Self::ChaCha20Poly1305),
+            2 => Some(Self::Aes256Gcm),
+            _ => None,
+        }
+    }
+
+    /// Encrypt the payload with the key and the nonce
+    fn encrypt(self, key: &[u8; 32], nonce: &[u8; NONCE_LENGTH], payload: Payload) -> Vec<u8> {
+        match self {
+            Self::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(key.into()).encrypt(nonce.into(), payload)
+            }
+            Self::Aes256Gcm => Aes256Gcm::new(key.into()).encrypt(nonce.into(), payload),
+        }
+        .expect("The buffer is allocated, can't fail")
+    }
+
+    /// Decrypt the payload with the key and the nonce
+    fn decrypt(
+        self,
+        key: &[u8; 32],
+        nonce: &[u8; NONCE_LENGTH],
+        payload: Payload,
+    ) -> Result<Vec<u8>> {
+        match self {
+            Self::ChaCha20Poly1305 => {
+                ChaCha20Poly1305::new(key.into()).decrypt(nonce.into(), payload)
+            }
+            Self::Aes256Gcm => Aes256Gcm::new(key.into()).decrypt(nonce.into(), payload),
+        }
+        .map_err(|_| CipherError::Decryption)
+    }
+}
+
+/// Returns true if the data starts with the envelope magic and version, the
+/// data is not authenticated here.
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.len() >= HEADER_LENGTH + TAG_LENGTH
+        && data.starts_with(MAGIC)
+        && data[MAGIC.len()] == VERSION
+}
+
+/// Encrypt the data with the key in a new envelope, with a random nonce.
+pub fn seal(
+    key: &[u8; 32],
+    algorithm: AeadAlgorithm,
+    associated_data: &[u8],
+    data: &[u8],
+) -> Vec<u8> {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    thread_rng().fill_bytes(&mut nonce);
+
+    let mut envelope =
+        Vec::with_capacity(HEADER_LENGTH + associated_data.len() + data.len() + TAG_LENGTH);
+    envelope.extend_from_slice(MAGIC);
+    envelope.push(VERSION);
+    envelope.push(algorithm.id());
+    envelope.extend_from_slice(&nonce);
+    envelope.extend_from_slice(
+        &u32::try_from(associated_data.len())
+            .expect("The associated data is too large")
+            .to_be_bytes(),
+    );
+    envelope.extend_from_slice(associated_data);
+
+    let ciphertext = algorithm.encrypt(
+        key,
+        &nonce,
+        Payload {
+            msg: data,
+            aad: &envelope,
+        },
+    );
+    envelope.extend(ciphertext);
+    envelope
+}
+
+/// Decrypt the envelope with the key.
+///
+/// ## Errors
+/// - [`CipherError::InvalidEnvelope`] if the envelope is malformed or its
+///   algorithm is unknown
+/// - [`CipherError::Decryption`] if the authentication failed
+pub fn open(key: &[u8; 32], envelope: &[u8]) -> Result<OpenedEnvelope> {
+    if !is_envelope(envelope) {
+        return Err(CipherError::InvalidEnvelope);
+    }
+    let algorithm =
+        AeadAlgorithm::from_id(envelope[MAGIC.len() + 1]).ok_or(CipherError::InvalidEnvelope)?;
+    let nonce: [u8; NONCE_LENGTH] = envelope[MAGIC.len() + 2..HEADER_LENGTH - 4]
+        .try_into()
+        .expect("The length is correct");
+    let associated_data_length = u32::from_be_bytes(
+        envelope[HEADER_LENGTH - 4..HEADER_LENGTH]
+            .try_into()
+            .expect("The length is correct"),
+    );
+    let header_length = usize::try_from(associated_data_length)
+        .ok()
+        .and_then(|length| length.checked_add(HEADER_LENGTH))
+        .filter(|length| envelope.len() >= length + TAG_LENGTH)
+        .ok_or(CipherError::InvalidEnvelope)?;
+    let (header, ciphertext) = envelope.split_at(header_length);
+
+    Ok(OpenedEnvelope {
+        algorithm,
+        associated_data: header[HEADER_LENGTH..].to_vec(),
+        data: algorithm.decrypt(
+            key,
+            &nonce,
+            Payload {
+                msg: ciphertext,
+                aad: header,
+            },
+        )?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_envelope, open, seal, AeadAlgorithm, HEADER_LENGTH, MAGIC};
+    use crate::cipher::CipherError;
+
+    const KEY: [u8; 32] = [7; 32];
+
+    #[test]
+    fn round_trip() {
+        for algorithm in [AeadAlgorithm::ChaCha20Poly1305, AeadAlgorithm::Aes256Gcm] {
+            let envelope = seal(&KEY, algorithm, b"associated", b"data");
+            let opened = open(&KEY, &envelope).expect("Is valid envelope");
+
+            assert_eq!(opened.algorithm, algorithm, "Wrong algorithm");
+            assert_eq!(
+                opened.associated_data, b"associated",
+                "Wrong associated data"
+            );
+            assert_eq!(opened.data, b"data", "Wrong data");
+        }
+    }
+
+    #[test]
+    fn empty_data() {
+        let envelope = seal(&KEY, AeadAlgorithm::default(), b"", b"");
+        assert!(is_envelope(&envelope), "The empty envelope is an envelope");
+        let opened = open(&KEY, &envelope).expect("Is valid envelope");
+        assert!(opened.data.is_empty(), "Wrong data");
+        assert!(opened.associated_data.is_empty(), "Wrong associated data");
+    }
+
+    #[test]
+    fn random_nonce() {
+        assert_ne!(
+            seal(&KEY, AeadAlgorithm::default(), b"", b"data"),
+            seal(&KEY, AeadAlgorithm::default(), b"", b"data"),
+            "Each envelope must have its own nonce"
+        );
+    }
+
+    #[test]
+    fn tampered_envelope() {
+        let envelope = seal(
+            &KEY,
+            AeadAlgorithm::ChaCha20Poly1305,
+            b"associated",
+            b"data",
+        );
+        // The nonce, the associated data, the ciphertext and the tag
+        for index in [
+            MAGIC.len() + 2,
+            HEADER_LENGTH,
+            HEADER_LENGTH + 10,
+            envelope.len() - 1,
+        ] {
+            let mut tampered = envelope.clone();
+            tampered[index] ^= 1;
+            assert!(
+                matches!(open(&KEY, &tampered), Err(CipherError::Decryption)),
+                "The envelope tampered at {index} must be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn changed_algorithm() {
+        let mut envelope = seal(&KEY, AeadAlgorithm::ChaCha20Poly1305, b"", b"data");
+        envelope[MAGIC.len() + 1] = AeadAlgorithm::Aes256Gcm.id();
+        assert!(
+            matches!(open(&KEY, &envelope), Err(CipherError::Decryption)),
+            "The envelope with a changed algorithm must be rejected"
+        );
+
+        envelope[MAGIC.len() + 1] = 0;
+        assert!(
+            matches!(open(&KEY, &envelope), Err(CipherError::InvalidEnvelope)),
+            "The envelope with an unknown algorithm must be rejected"
+        );
+    }
+
+    #[test]
+    fn wrong_key() {
+        let envelope = seal(&KEY, AeadAlgorithm::default(), b"", b"data");
+        assert!(
+            matches!(open(&[8; 32], &envelope), Err(CipherError::Decryption)),
+            "The envelope must not be opened with another key"
+        );
+    }
+
+    #[test]
+    fn malformed_envelope() {
+        let envelope = seal(&KEY, AeadAlgorithm::default(), b"associated", b"data");
+        assert!(
+            matches!(
+                open(&KEY, &envelope[..HEADER_LENGTH]),
+                Err(CipherError::InvalidEnvelope)
+            ),
+            "The truncated envelope must be rejected"
+        );
+
+        let mut envelope = envelope;
+        // Associated data longer than the envelope
+        envelope[HEADER_LENGTH - 4..HEADER_LENGTH].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(
+            matches!(open(&KEY, &envelope), Err(CipherError::InvalidEnvelope)),
+            "The envelope with a wrong associated data length must be rejected"
+        );
+
+        assert!(
+            !is_envelope(b"not an envelope, only some bytes"),
+            "The data without the magic is not an envelope"
+        );
+    }
+}
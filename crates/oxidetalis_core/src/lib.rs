@@ -21,7 +21,9 @@
 
 //! The core library for the OxideTalis homeserver implementation.
 
+pub mod canonical;
 pub mod cipher;
+pub mod envelope;
 pub mod types;
 
 /// The header name for the signature. The signature is a hex encoded string.
@@ -24,11 +24,22 @@
 use std::{fmt, str::FromStr};
 
 use base58::{FromBase58, ToBase58};
+use k256::ecdsa::{
+    signature::Verifier,
+    Signature as EcdsaSignature,
+    VerifyingKey as EcdsaVerifyingKey,
+};
 
 use crate::cipher::{hmac_sha256, CipherError};
 
 /// Correct length except message
 const CORRECT_LENGTH: &str = "The length is correct";
+/// Length of the HMAC signature, it has no version byte
+const HMAC_SIGNATURE_LENGTH: usize = 56;
+/// Length of the ECDSA signature, with its version byte
+const ECDSA_SIGNATURE_LENGTH: usize = 89;
+/// Version byte of the ECDSA signature
+const ECDSA_VERSION_BYTE: u8 = 1;
 
 /// K256 public key
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
@@ -38,12 +49,26 @@ pub struct PublicKey([u8; 33]);
 #[derive(Clone, Copy)]
 pub struct PrivateKey([u8; 32]);
 
+/// Version of the OTMP signature
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum SignatureVersion {
+    /// HMAC-SHA256 with the ECDH shared secret between the signer and the
+    /// verifier, so both of them can produce it. Encoded without a version
+    /// byte, `hmac || timestamp || nonce`
+    Hmac,
+    /// ECDSA on secp256k1 of `timestamp || nonce || data`, only the signer can
+    /// produce it. Encoded as `0x01 || r || s || timestamp || nonce`
+    Ecdsa,
+}
+
 /// OTMP signature
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
 pub struct Signature {
-    hmac_output: [u8; 32],
-    timestamp:   [u8; 8],
-    nonce:       [u8; 16],
+    hmac_output:  [u8; 32],
+    timestamp:    [u8; 8],
+    nonce:        [u8; 16],
+    /// ECDSA output `r || s`, the HMAC output is zeroed if it's set
+    ecdsa_output: Option<[u8; 64]>,
 }
 
 impl PublicKey {
@@ -61,11 +86,37 @@ impl PrivateKey {
 }
 
 impl Signature {
-    /// Returns the hmac output from the signature
+    /// Creates an ECDSA signature from its `r || s` output
+    pub(crate) const fn ecdsa(output: [u8; 64], timestamp: [u8; 8], nonce: [u8; 16]) -> Self {
+        Self {
+            hmac_output: [0; 32],
+            timestamp,
+            nonce,
+            ecdsa_output: Some(output),
+        }
+    }
+
+    /// Returns the signature version
+    pub const fn version(&self) -> SignatureVersion {
+        if self.ecdsa_output.is_some() {
+            SignatureVersion::Ecdsa
+        } else {
+            SignatureVersion::Hmac
+        }
+    }
+
+    /// Returns the hmac output from the signature, it's zeroed for the ECDSA
+    /// signature
     pub const fn hmac_output(&self) -> &[u8; 32] {
         &self.hmac_output
     }
 
+    /// Returns the ECDSA output `r || s` from the signature, if it's an ECDSA
+    /// signature
+    pub const fn ecdsa_output(&self) -> Option<&[u8; 64]> {
+        self.ecdsa_output.as_ref()
+    }
+
     /// Returns the timestamp from the signature
     pub const fn timestamp(&self) -> &[u8; 8] {
         &self.timestamp
@@ -76,7 +127,8 @@ impl Signature {
         &self.nonce
     }
 
-    /// Returns the signature as bytes
+    /// Returns the HMAC signature as bytes, use [`Signature::to_bytes`] to
+    /// encode a signature of any version
     pub fn as_bytes(&self) -> [u8; 56] {
         let mut sig = [0u8; 56];
         sig[0..=31].copy_from_slice(&self.hmac_output);
@@ -85,8 +137,30 @@ impl Signature {
         sig
     }
 
-    /// Verify the signature with the given shared secret.
+    /// Returns the ECDSA signature as bytes, if it's an ECDSA signature
+    pub fn ecdsa_bytes(&self) -> Option<[u8; ECDSA_SIGNATURE_LENGTH]> {
+        let output = self.ecdsa_output.as_ref()?;
+        let mut sig = [0u8; ECDSA_SIGNATURE_LENGTH];
+        sig[0] = ECDSA_VERSION_BYTE;
+        sig[1..=64].copy_from_slice(output);
+        sig[65..=72].copy_from_slice(&self.timestamp);
+        sig[73..=88].copy_from_slice(&self.nonce);
+        Some(sig)
+    }
+
+    /// Returns the signature as bytes, see [`SignatureVersion`] for the
+    /// encoding of each version
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.ecdsa_bytes()
+            .map_or_else(|| self.as_bytes().to_vec(), |sig| sig.to_vec())
+    }
+
+    /// Verify the HMAC signature with the given shared secret, returns false
+    /// if it's not an HMAC signature.
     pub fn verify(&self, data: &[u8], shared_secret: &[u8; 32]) -> bool {
+        if self.version() != SignatureVersion::Hmac {
+            return false;
+        }
         let mut hmac_secret = [0u8; 56];
         hmac_secret[0..=31].copy_from_slice(shared_secret);
         hmac_secret[32..=39].copy_from_slice(self.timestamp());
@@ -94,6 +168,32 @@ impl Signature {
 
         &hmac_sha256(data, &hmac_secret) == self.hmac_output()
     }
+
+    /// Verify the ECDSA signature with the signer public key, returns false if
+    /// it's not an ECDSA signature.
+    pub fn verify_ecdsa(&self, data: &[u8], signer: &PublicKey) -> bool {
+        let Some(output) = self.ecdsa_output() else {
+            return false;
+        };
+        let (Ok(verifying_key), Ok(signature)) = (
+            EcdsaVerifyingKey::from_sec1_bytes(signer.as_bytes()),
+            EcdsaSignature::from_slice(output),
+        ) else {
+            return false;
+        };
+        verifying_key
+            .verify(
+                &ecdsa_signed_data(data, &self.timestamp, &self.nonce),
+                &signature,
+            )
+            .is_ok()
+    }
+}
+
+/// Returns the data that the ECDSA signature signs, `timestamp || nonce ||
+/// data`
+pub(crate) fn ecdsa_signed_data(data: &[u8], timestamp: &[u8; 8], nonce: &[u8; 16]) -> Vec<u8> {
+    [timestamp.as_slice(), nonce, data].concat()
 }
 
 /// Public key to base58 string
@@ -113,7 +213,7 @@ impl fmt::Display for PrivateKey {
 /// Signature to hex string
 impl fmt::Display for Signature {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", hex::encode(self.as_bytes()))
+        write!(f, "{}", hex::encode(self.to_bytes()))
     }
 }
 
@@ -154,12 +254,7 @@ impl FromStr for Signature {
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let signature = hex::decode(s).map_err(|_| CipherError::InvalidHex(s.to_owned()))?;
-        if signature.len() != 56 {
-            return Err(CipherError::InvalidSignature);
-        }
-        Ok(Signature::from(
-            <[u8; 56]>::try_from(signature).expect(CORRECT_LENGTH),
-        ))
+        Self::try_from(signature.as_slice())
     }
 }
 
@@ -191,9 +286,33 @@ impl TryFrom<[u8; 32]> for PrivateKey {
 impl From<[u8; 56]> for Signature {
     fn from(signature: [u8; 56]) -> Self {
         Self {
-            hmac_output: signature[0..=31].try_into().expect(CORRECT_LENGTH),
-            timestamp:   signature[32..=39].try_into().expect(CORRECT_LENGTH),
-            nonce:       signature[40..=55].try_into().expect(CORRECT_LENGTH),
+            hmac_output:  signature[0..=31].try_into().expect(CORRECT_LENGTH),
+            timestamp:    signature[32..=39].try_into().expect(CORRECT_LENGTH),
+            nonce:        signature[40..=55].try_into().expect(CORRECT_LENGTH),
+            ecdsa_output: None,
+        }
+    }
+}
+
+/// Signature from bytes, the legacy HMAC signature has no version byte
+impl TryFrom<&[u8]> for Signature {
+    type Error = CipherError;
+
+    fn try_from(signature: &[u8]) -> Result<Self, Self::Error> {
+        match (signature.len(), signature.first()) {
+            (HMAC_SIGNATURE_LENGTH, _) => {
+                Ok(Self::from(
+                    <[u8; 56]>::try_from(signature).expect(CORRECT_LENGTH),
+                ))
+            }
+            (ECDSA_SIGNATURE_LENGTH, Some(&ECDSA_VERSION_BYTE)) => {
+                Ok(Self::ecdsa(
+                    signature[1..=64].try_into().expect(CORRECT_LENGTH),
+                    signature[65..=72].try_into().expect(CORRECT_LENGTH),
+                    signature[73..=88].try_into().expect(CORRECT_LENGTH),
+                ))
+            }
+            _ => Err(CipherError::InvalidSignature),
         }
     }
 }
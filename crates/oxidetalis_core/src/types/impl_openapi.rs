@@ -39,7 +39,7 @@ use salvo_oapi::{
     ToSchema,
 };
 
-use super::{PublicKey as CorePublicKey, Signature};
+use super::{FederatedUserId, PublicKey as CorePublicKey, Signature};
 
 impl ToSchema for CorePublicKey {
     fn to_schema(_components: &mut salvo_oapi::Components) -> salvo_oapi::RefOr<OapiSchema> {
@@ -61,18 +61,30 @@ impl ToSchema for Signature {
     fn to_schema(_components: &mut salvo_oapi::Components) -> salvo_oapi::RefOr<OapiSchema> {
         salvo_oapi::Object::new()
             .name(crate::SIGNATURE_HEADER)
-            .description("Signature of the request")
+            .description("Signature of the canonical form of the request")
             .schema_type(OapiSchemaType::String)
             .format(OapiSchemaFormat::Custom("hex".to_owned()))
             .required(crate::SIGNATURE_HEADER)
-            // 56 bytes in hex (valid signature)
+            // 56 bytes HMAC or 89 bytes ECDSA signature in hex
             .example("0".repeat(112).into())
-            .max_length(112)
+            .max_length(178)
             .min_length(112)
             .into()
     }
 }
 
+impl ToSchema for FederatedUserId {
+    fn to_schema(_components: &mut salvo_oapi::Components) -> salvo_oapi::RefOr<OapiSchema> {
+        salvo_oapi::Object::new()
+            .name("FederatedUserId")
+            .description("User's public key and their homeserver, `base58key@server`")
+            .schema_type(OapiSchemaType::String)
+            .format(OapiSchemaFormat::Custom("otmp-user-id".to_owned()))
+            .example("rW8FMG5D75NVNJV3Wd498dEh65BgUuhwY1Yk5zYJPpRe@example.com".into())
+            .into()
+    }
+}
+
 impl<'ex> Extractible<'ex> for CorePublicKey {
     fn metadata() -> &'ex ExtractMetadata {
         static METADATA: ExtractMetadata = ExtractMetadata::new("");
@@ -140,7 +152,7 @@ impl ToParameters<'_> for Signature {
             Parameter::new(crate::SIGNATURE_HEADER)
                 .parameter_in(ParameterIn::Header)
                 .required(true)
-                .description("Signature of the request")
+                .description("Signature of the canonical form of the request")
                 .example("0".repeat(112).into())
                 .schema(Self::to_schema(components)),
         )
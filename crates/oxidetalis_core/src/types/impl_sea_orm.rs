@@ -23,7 +23,7 @@
 //! as column types in SeaORM
 
 use sea_orm::{
-    sea_query::{ArrayType, BlobSize, ValueType, ValueTypeErr},
+    sea_query::{ArrayType, BlobSize, Nullable, ValueType, ValueTypeErr},
     ColumnType,
     DbErr,
     QueryResult,
@@ -32,7 +32,7 @@ use sea_orm::{
     Value,
 };
 
-use super::PublicKey;
+use super::{FederatedUserId, PublicKey, Signature};
 
 impl From<PublicKey> for Value {
     fn from(public_key: PublicKey) -> Self {
@@ -82,3 +82,90 @@ impl ValueType for PublicKey {
         ColumnType::Binary(BlobSize::Blob(None))
     }
 }
+
+impl From<Signature> for Value {
+    fn from(signature: Signature) -> Self {
+        signature.to_bytes().into()
+    }
+}
+
+impl From<&Signature> for Value {
+    fn from(signature: &Signature) -> Self {
+        signature.to_bytes().into()
+    }
+}
+
+impl Nullable for Signature {
+    fn null() -> Value {
+        Value::Bytes(None)
+    }
+}
+
+impl TryGetable for Signature {
+    fn try_get_by<I: sea_orm::ColIdx>(res: &QueryResult, idx: I) -> Result<Self, TryGetError> {
+        <Vec<u8> as TryGetable>::try_get_by(res, idx).and_then(|v| {
+            <Signature as TryFrom<&[u8]>>::try_from(v.as_slice())
+                .map_err(|_| TryGetError::DbErr(DbErr::Type("Invalid Signature".to_owned())))
+        })
+    }
+}
+
+impl ValueType for Signature {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        <Vec<u8> as ValueType>::try_from(v).and_then(|v| {
+            <Signature as TryFrom<&[u8]>>::try_from(v.as_slice()).map_err(|_| ValueTypeErr)
+        })
+    }
+
+    fn type_name() -> String {
+        String::from("Signature")
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::Bytes
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Binary(BlobSize::Blob(None))
+    }
+}
+
+impl From<FederatedUserId> for Value {
+    fn from(user_id: FederatedUserId) -> Self {
+        user_id.to_string().into()
+    }
+}
+
+impl From<&FederatedUserId> for Value {
+    fn from(user_id: &FederatedUserId) -> Self {
+        user_id.to_string().into()
+    }
+}
+
+impl TryGetable for FederatedUserId {
+    fn try_get_by<I: sea_orm::ColIdx>(res: &QueryResult, idx: I) -> Result<Self, TryGetError> {
+        <String as TryGetable>::try_get_by(res, idx).and_then(|v| {
+            v.parse().map_err(|_| {
+                TryGetError::DbErr(DbErr::Type("Invalid Federated User Id".to_owned()))
+            })
+        })
+    }
+}
+
+impl ValueType for FederatedUserId {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        <String as ValueType>::try_from(v).and_then(|v| v.parse().map_err(|_| ValueTypeErr))
+    }
+
+    fn type_name() -> String {
+        String::from("FederatedUserId")
+    }
+
+    fn array_type() -> ArrayType {
+        ArrayType::String
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::String(None)
+    }
+}
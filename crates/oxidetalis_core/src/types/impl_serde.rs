@@ -24,7 +24,7 @@
 use base58::FromBase58;
 use serde::{de::Error as DeError, Deserialize, Serialize};
 
-use super::{PrivateKey, PublicKey, Signature};
+use super::{FederatedUserId, PrivateKey, PublicKey, Signature};
 use crate::cipher::K256Secret;
 
 impl<'de> Deserialize<'de> for PrivateKey {
@@ -86,9 +86,9 @@ impl<'de> Deserialize<'de> for Signature {
     {
         let signature = hex::decode(String::deserialize(deserializer)?)
             .map_err(|_| DeError::custom("Invalid hex string"))?;
-        Ok(Self::from(<[u8; 56]>::try_from(signature).map_err(
-            |_| DeError::custom("Invalid signature length, must be 56 bytes"),
-        )?))
+        Self::try_from(signature.as_slice()).map_err(|_| {
+            DeError::custom("Invalid signature, must be 56 bytes HMAC or 89 bytes ECDSA signature")
+        })
     }
 }
 
@@ -118,3 +118,23 @@ impl<'de> Deserialize<'de> for K256Secret {
         Ok(Self::from_privkey(&PrivateKey::deserialize(deserializer)?))
     }
 }
+
+impl<'de> Deserialize<'de> for FederatedUserId {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(DeError::custom)
+    }
+}
+
+impl Serialize for FederatedUserId {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
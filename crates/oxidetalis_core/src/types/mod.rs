@@ -29,6 +29,8 @@ mod impl_sea_orm;
 #[cfg(feature = "serde")]
 mod impl_serde;
 mod size;
+mod user_id;
 
 pub use cipher::*;
 pub use size::*;
+pub use user_id::*;
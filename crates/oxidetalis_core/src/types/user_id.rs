@@ -0,0 +1,239 @@
+// OxideTalis Messaging Protocol homeserver core implementation
+// Copyright (C) 2024 Awiteb <a@4rs.nl>, OxideTalis Contributors
+//
+// Permission is hereby granted, free of charge, to any person obtaining a copy
+// of this software and associated documentation files (the "Software"), to deal
+// in the Software without restriction, including without limitation the rights
+// to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+// copies of the Software, and to permit persons to whom the Software is
+// furnished to do so, subject to the following conditions:
+//
+// The above copyright notice and this permission notice shall be included in
+// all copies or substantial portions of the Software.
+//
+// THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+// IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+// FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+// AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+// LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+// OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+// SOFTWARE.
+
+//! Federated user identifier, a user public key with their homeserver.
+
+use std::{fmt, str::FromStr};
+
+use url::Host;
+
+use super::PublicKey;
+use crate::cipher::CipherError;
+
+/// Federated user identifier errors
+#[derive(Debug, thiserror::Error)]
+pub enum UserIdError {
+    /// The identifier has no `@` separator
+    #[error("Missing the `@` separator of the user identifier")]
+    MissingSeparator,
+    /// Invalid public key part
+    #[error("Invalid user public key: {0}")]
+    InvalidPublicKey(#[from] CipherError),
+    /// Invalid server host part
+    #[error("Invalid server host: {0}")]
+    InvalidHost(#[from] url::ParseError),
+    /// Invalid server port part
+    #[error("Invalid server port `{0}`")]
+    InvalidPort(String),
+}
+
+/// Federated user identifier, in the form `base58key@server`, the server is the
+/// host of the user homeserver with an optional port, e.g.
+/// `rW8FMG5D75NVNJV3Wd498dEh65BgUuhwY1Yk5zYJPpRe@example.com`
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FederatedUserId {
+    /// The user public key
+    pub public_key: PublicKey,
+    /// The host of the user homeserver, the domains are lowercased
+    pub host:       Host,
+    /// The port of the user homeserver, if it's not the default one
+    pub port:       Option<u16>,
+}
+
+impl FederatedUserId {
+    /// Creates new [`FederatedUserId`]
+    pub const fn new(public_key: PublicKey, host: Host, port: Option<u16>) -> Self {
+        Self {
+            public_key,
+            host,
+            port,
+        }
+    }
+
+    /// Returns the name of the user homeserver, the host with the port if
+    /// there is one
+    pub fn server(&self) -> String {
+        self.port.map_or_else(
+            || self.host.to_string(),
+            |port| format!("{}:{port}", self.host),
+        )
+    }
+}
+
+impl FromStr for FederatedUserId {
+    type Err = UserIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (public_key, server) = s.split_once('@').ok_or(UserIdError::MissingSeparator)?;
+        // The IPv6 hosts are in brackets, so the last colon outside of them is
+        // the port separator
+        let (host, port) = match server.rsplit_once(':') {
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => {
+                let port = port
+                    .parse()
+                    .map_err(|_| UserIdError::InvalidPort(port.to_owned()))?;
+                (host, Some(port))
+            }
+            _ => (server, None),
+        };
+
+        Ok(Self::new(
+            PublicKey::from_str(public_key)?,
+            Host::parse(host)?,
+            port,
+        ))
+    }
+}
+
+impl fmt::Display for FederatedUserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}@{}", self.public_key, self.server())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::{Ipv4Addr, Ipv6Addr},
+        str::FromStr,
+    };
+
+    use url::Host;
+
+    use super::{FederatedUserId, UserIdError};
+    use crate::types::PublicKey;
+
+    const PUBLIC_KEY: &str = "z29WdRwpsP3Jox66kVWof7S1z3XqW8VAdp7qAeiCL5cd";
+
+    fn public_key() -> PublicKey {
+        PublicKey::from_str(PUBLIC_KEY).expect("Is valid public key")
+    }
+
+    fn parse(server: &str) -> Result<FederatedUserId, UserIdError> {
+        FederatedUserId::from_str(&format!("{PUBLIC_KEY}@{server}"))
+    }
+
+    #[test]
+    fn parse_domain() {
+        let user_id = parse("Example.COM").expect("Is valid user id");
+        assert_eq!(user_id.public_key, public_key(), "Wrong public key");
+        assert_eq!(
+            user_id.host,
+            Host::Domain("example.com".to_owned()),
+            "The domain must be lowercased"
+        );
+        assert_eq!(user_id.port, None, "There is no port");
+        assert_eq!(user_id.server(), "example.com", "Wrong server name");
+    }
+
+    #[test]
+    fn parse_domain_with_port() {
+        let user_id = parse("example.com:8443").expect("Is valid user id");
+        assert_eq!(user_id.port, Some(8443), "Wrong port");
+        assert_eq!(user_id.server(), "example.com:8443", "Wrong server name");
+        assert_eq!(
+            user_id.to_string(),
+            format!("{PUBLIC_KEY}@example.com:8443"),
+            "The user id must be displayed as it's parsed"
+        );
+    }
+
+    #[test]
+    fn parse_ipv4() {
+        let user_id = parse("127.0.0.1:80").expect("Is valid user id");
+        assert_eq!(
+            user_id.host,
+            Host::<String>::Ipv4(Ipv4Addr::LOCALHOST),
+            "Wrong IPv4 host"
+        );
+        assert_eq!(user_id.port, Some(80), "Wrong port");
+    }
+
+    #[test]
+    fn parse_ipv6() {
+        let user_id = parse("[::1]").expect("Is valid user id");
+        assert_eq!(
+            user_id.host,
+            Host::<String>::Ipv6(Ipv6Addr::LOCALHOST),
+            "Wrong IPv6 host"
+        );
+        assert_eq!(user_id.port, None, "The IPv6 colons are not a port");
+        assert_eq!(user_id.server(), "[::1]", "Wrong server name");
+
+        let user_id = parse("[::1]:8080").expect("Is valid user id");
+        assert_eq!(user_id.port, Some(8080), "Wrong port");
+        assert_eq!(user_id.server(), "[::1]:8080", "Wrong server name");
+    }
+
+    #[test]
+    fn missing_separator() {
+        assert!(
+            matches!(
+                FederatedUserId::from_str(PUBLIC_KEY),
+                Err(UserIdError::MissingSeparator)
+            ),
+            "The user id without `@` must be rejected"
+        );
+    }
+
+    #[test]
+    fn empty_host() {
+        assert!(
+            matches!(parse(""), Err(UserIdError::InvalidHost(_))),
+            "The user id `key@` must be rejected"
+        );
+    }
+
+    #[test]
+    fn empty_port() {
+        assert!(
+            matches!(parse("example.com:"), Err(UserIdError::InvalidPort(port)) if port.is_empty()),
+            "The user id `key@host:` must be rejected"
+        );
+    }
+
+    #[test]
+    fn invalid_port() {
+        assert!(
+            matches!(parse("example.com:65536"), Err(UserIdError::InvalidPort(_))),
+            "The port must fit in 16 bits"
+        );
+    }
+
+    #[test]
+    fn unclosed_ipv6() {
+        assert!(
+            parse("[::1").is_err(),
+            "The IPv6 host without the closing bracket must be rejected"
+        );
+    }
+
+    #[test]
+    fn invalid_public_key() {
+        assert!(
+            matches!(
+                FederatedUserId::from_str("invalid@example.com"),
+                Err(UserIdError::InvalidPublicKey(_))
+            ),
+            "The invalid public key must be rejected"
+        );
+    }
+}
@@ -22,7 +22,7 @@
 //! Entity for `incoming_chat` table
 
 use chrono::Utc;
-use oxidetalis_core::types::PublicKey;
+use oxidetalis_core::types::{PublicKey, Signature};
 use sea_orm::entity::prelude::*;
 
 use crate::prelude::*;
@@ -35,11 +35,16 @@ pub struct Model {
     pub recipient_id:       IdCol,
     /// Public key of the sender
     pub sender:             PublicKey,
+    /// The homeserver of the sender, `None` if the sender is a local user
+    pub sender_server:      Option<String>,
     /// Whether the chat response accepted or not.
     /// This will be `None` if it is chat request, otherwise `bool`
     pub accepted_response:  Option<bool>,
     /// The timestamp of the request, when it was received
     pub received_timestamp: chrono::DateTime<Utc>,
+    /// The sender ECDSA signature of their chat request, `None` if it's not
+    /// signed with ECDSA or it's a chat response
+    pub signature:          Option<Signature>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -21,8 +21,11 @@
 
 #![doc = include_str!("../README.md")]
 
+pub mod federation_outbox;
 pub mod incoming_chat;
+pub mod invite_tokens;
 pub mod outgoing_chat_requests;
 pub mod prelude;
+pub mod queued_events;
 pub mod users;
 pub mod users_status;
@@ -31,12 +31,15 @@ use crate::prelude::*;
 #[sea_orm(table_name = "out_chat_requests")]
 pub struct Model {
     #[sea_orm(primary_key)]
-    pub id:        IdCol,
-    pub sender_id: IdCol,
+    pub id:               IdCol,
+    pub sender_id:        IdCol,
     /// Public key of the recipient
-    pub recipient: PublicKey,
+    pub recipient:        PublicKey,
+    /// The homeserver of the recipient, `None` if the recipient is a local
+    /// user
+    pub recipient_server: Option<String>,
     /// The timestamp of the request, when it was sent
-    pub out_on:    chrono::DateTime<Utc>,
+    pub out_on:           chrono::DateTime<Utc>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -40,18 +40,36 @@ pub use sea_orm::{
 /// User ID type
 pub(crate) type IdCol = i64;
 
+pub use super::federation_outbox::{
+    ActiveModel as FederationOutboxActiveModel,
+    Column as FederationOutboxColumn,
+    Entity as FederationOutboxEntity,
+    Model as FederationOutboxModel,
+};
 pub use super::incoming_chat::{
     ActiveModel as IncomingChatActiveModel,
     Column as IncomingChatColumn,
     Entity as IncomingChatEntity,
     Model as IncomingChatModel,
 };
+pub use super::invite_tokens::{
+    ActiveModel as InviteTokensActiveModel,
+    Column as InviteTokensColumn,
+    Entity as InviteTokensEntity,
+    Model as InviteTokensModel,
+};
 pub use super::outgoing_chat_requests::{
     ActiveModel as OutChatRequestsActiveModel,
     Column as OutChatRequestsColumn,
     Entity as OutChatRequestsEntity,
     Model as OutChatRequestsModel,
 };
+pub use super::queued_events::{
+    ActiveModel as QueuedEventsActiveModel,
+    Column as QueuedEventsColumn,
+    Entity as QueuedEventsEntity,
+    Model as QueuedEventsModel,
+};
 pub use super::users::{
     ActiveModel as UserActiveModel,
     Column as UserColumn,
@@ -35,6 +35,8 @@ pub struct Model {
     pub public_key:  PublicKey,
     pub last_logout: chrono::DateTime<Utc>,
     pub is_admin:    bool,
+    /// Whether the user is banned from the homeserver
+    pub is_banned:   bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -45,6 +47,12 @@ pub enum Relation {
     OutChatRequests,
     #[sea_orm(has_many = "UsersStatusEntity")]
     UsersStatus,
+    #[sea_orm(has_many = "QueuedEventsEntity")]
+    QueuedEvents,
+    #[sea_orm(has_many = "FederationOutboxEntity")]
+    FederationOutbox,
+    #[sea_orm(has_many = "InviteTokensEntity")]
+    InviteTokens,
 }
 
 impl Related<IncomingChatEntity> for Entity {
@@ -65,4 +73,22 @@ impl Related<UsersStatusEntity> for Entity {
     }
 }
 
+impl Related<QueuedEventsEntity> for Entity {
+    fn to() -> RelationDef {
+        Relation::QueuedEvents.def()
+    }
+}
+
+impl Related<FederationOutboxEntity> for Entity {
+    fn to() -> RelationDef {
+        Relation::FederationOutbox.def()
+    }
+}
+
+impl Related<InviteTokensEntity> for Entity {
+    fn to() -> RelationDef {
+        Relation::InviteTokens.def()
+    }
+}
+
 impl ActiveModelBehavior for ActiveModel {}
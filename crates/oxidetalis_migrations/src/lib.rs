@@ -24,8 +24,15 @@
 use sea_orm_migration::prelude::*;
 pub use sea_orm_migration::MigratorTrait;
 
+mod add_is_banned_to_users;
+mod add_recipient_server_to_out_chat_requests;
+mod add_sender_server_to_incoming_chat;
+mod add_signature_to_incoming_chat;
+mod create_federation_outbox_table;
 mod create_incoming_chat_table;
+mod create_invite_tokens_table;
 mod create_outgoing_chat_requests_table;
+mod create_queued_events_table;
 mod create_users_status;
 mod create_users_table;
 
@@ -39,6 +46,13 @@ impl MigratorTrait for Migrator {
             Box::new(create_incoming_chat_table::Migration),
             Box::new(create_outgoing_chat_requests_table::Migration),
             Box::new(create_users_status::Migration),
+            Box::new(create_queued_events_table::Migration),
+            Box::new(add_sender_server_to_incoming_chat::Migration),
+            Box::new(create_federation_outbox_table::Migration),
+            Box::new(add_is_banned_to_users::Migration),
+            Box::new(create_invite_tokens_table::Migration),
+            Box::new(add_recipient_server_to_out_chat_requests::Migration),
+            Box::new(add_signature_to_incoming_chat::Migration),
         ]
     }
 }